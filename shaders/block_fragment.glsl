@@ -1,11 +1,37 @@
 #version 140
 
 in vec2 fragment_uv;
+flat in vec4 fragment_tile;
+in vec2 fragment_light;
+in float fragment_occlusion;
+in float fragment_distance;
 
 out vec4 color;
 
 uniform sampler2D sampler;
+// fragments with less alpha are discarded, which cuts the holes out of blocks like leaves
+uniform float alpha_threshold;
+// how bright the sky is at the current time of day, from 0 to 1
+uniform float daylight;
+uniform float fog_start;
+uniform float fog_end;
+uniform vec3 fog_color;
 
 void main() {
-    color = texture(sampler, fragment_uv);
-}
\ No newline at end of file
+    // the texture repeats on quads that span several blocks; the gradients are taken from the
+    // unwrapped coordinates so that the jump at each repeat doesn't select the smallest mipmap
+    vec2 tile_uv = fragment_tile.xy + fract(fragment_uv) * fragment_tile.zw;
+    vec2 gradient_uv = fragment_uv * fragment_tile.zw;
+    color = textureGrad(sampler, tile_uv, dFdx(gradient_uv), dFdy(gradient_uv));
+
+    if (color.a < alpha_threshold) {
+        discard;
+    }
+
+    // every light level is 80% as bright as the one above it
+    float level = max(fragment_light.x * daylight, fragment_light.y);
+    color.rgb *= pow(0.8, (1.0 - level) * 15.0) * fragment_occlusion;
+
+    float fog = smoothstep(fog_start, fog_end, fragment_distance);
+    color.rgb = mix(color.rgb, fog_color, fog);
+}
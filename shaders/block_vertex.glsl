@@ -2,20 +2,25 @@
 
 uniform mat4 projection_matrix;
 uniform mat4 view_matrix;
-uniform float total_blocks;
 
-in mat4 matrix;
 in vec3 position;
 in vec2 uv;
-in int face;
-in int id;
+in vec4 tile;
+in vec2 light;
+in float occlusion;
 
 out vec2 fragment_uv;
+flat out vec4 fragment_tile;
+out vec2 fragment_light;
+out float fragment_occlusion;
+out float fragment_distance;
 
 void main() {
-	float theX = (uv.x / total_blocks ) + ((id - 1) / total_blocks);
-	float theY = 1.0 - (1.0 - uv.y + face) / 6.0;
-
-    fragment_uv = vec2(theX, theY);
-    gl_Position = projection_matrix * view_matrix * matrix * vec4(position, 1.0);
+    fragment_uv = uv;
+    fragment_tile = tile;
+    fragment_light = light;
+    fragment_occlusion = occlusion;
+    vec4 view_position = view_matrix * vec4(position, 1.0);
+    fragment_distance = length(view_position.xyz);
+    gl_Position = projection_matrix * view_position;
 }
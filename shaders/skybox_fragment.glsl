@@ -1,10 +1,44 @@
 #version 140
 
-uniform samplerCube cubemap;
+uniform vec3 zenith_color;
+uniform vec3 horizon_color;
+// points towards the sun, the moon is on the opposite side
+uniform vec3 sun_direction;
+uniform float star_brightness;
 
-in vec3 fragment_uv;
+in vec3 fragment_direction;
 out vec4 color;
 
+const float SUN_SIZE = 0.9994;
+const float MOON_SIZE = 0.9996;
+const float STAR_DENSITY = 0.9975;
+
+// a pseudo-random number from 0 to 1 for every cell of a grid
+float hash(vec3 cell) {
+    return fract(sin(dot(cell, vec3(12.9898, 78.233, 45.164))) * 43758.5453);
+}
+
 void main() {
-    color = texture(cubemap, fragment_uv);
-}
\ No newline at end of file
+    vec3 direction = normalize(fragment_direction);
+
+    vec3 sky = mix(horizon_color, zenith_color, clamp(direction.y, 0.0, 1.0));
+
+    // the stars turn along with the sun and moon, so they're looked up relative to the sun
+    vec3 star_direction = vec3(
+        dot(direction.xy, sun_direction.xy),
+        dot(direction.xy, vec2(-sun_direction.y, sun_direction.x)),
+        direction.z
+    );
+    float star = step(STAR_DENSITY, hash(floor(star_direction * 200.0)));
+    sky += vec3(star * star_brightness * clamp(direction.y * 4.0, 0.0, 1.0));
+
+    float sun = dot(direction, sun_direction);
+    sky += vec3(1.0, 0.8, 0.5) * pow(max(sun, 0.0), 200.0) * 0.5;
+    if (sun > SUN_SIZE) {
+        sky = vec3(1.0, 0.95, 0.8);
+    } else if (-sun > MOON_SIZE) {
+        sky = vec3(0.85, 0.87, 0.9);
+    }
+
+    color = vec4(sky, 1.0);
+}
@@ -0,0 +1,261 @@
+use block_registry::BlockRegistryError;
+use game::Blocks;
+use image::{self, RgbaImage};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+pub const BLOCK_TEXTURES_DIRECTORY: &str = "textures/blocks";
+
+/// A texture's rectangle in the atlas as `[u, v, width, height]`. The atlas is uploaded upside
+/// down, so `v` is the top edge and `height` is negative.
+pub type AtlasRect = [f32; 4];
+
+/// Block textures packed into a single image, so that a chunk can be drawn without switching textures.
+pub struct TextureAtlas {
+    pub image: RgbaImage,
+    rects: HashMap<String, AtlasRect>,
+}
+
+impl TextureAtlas {
+    pub fn get_rect(&self, texture: &str) -> Option<AtlasRect> {
+        self.rects.get(texture).cloned()
+    }
+
+    /// Looks up the rectangle of every block face, failing if a block uses a texture that isn't in the atlas.
+    pub fn get_block_tiles(&self, blocks: &Blocks) -> Result<BlockTiles, BlockRegistryError> {
+        let mut tiles = Vec::with_capacity(blocks.block_map.len());
+
+        for block in blocks.block_map.iter() {
+            let mut faces = [[0.0; 4]; 6];
+            // ids without a block keep empty rectangles, they never end up in a chunk
+            let block = match *block {
+                Some(ref block) => block,
+                None => {
+                    tiles.push(faces);
+                    continue;
+                }
+            };
+
+            for (face, texture) in block.textures.iter().enumerate() {
+                faces[face] = self.get_rect(texture).ok_or_else(|| BlockRegistryError::UnknownTexture {
+                    block: block.name.clone(),
+                    texture: texture.clone(),
+                })?;
+            }
+
+            tiles.push(faces);
+        }
+
+        Ok(BlockTiles { tiles: tiles })
+    }
+}
+
+/// The atlas rectangle of every face of every block, which is all the mesher needs to know about textures.
+#[derive(Clone)]
+pub struct BlockTiles {
+    tiles: Vec<[AtlasRect; 6]>,
+}
+
+impl BlockTiles {
+    pub fn get(&self, block: u16, face: usize) -> AtlasRect {
+        self.tiles[block as usize][face]
+    }
+}
+
+/// Packs the textures into rows, tallest first, in an atlas whose sides are powers of two. Every
+/// texture is surrounded by `padding` pixels copied from its edges, so that neither filtering nor
+/// the smaller mipmap levels blend it with its neighbours. Textures of the same height are placed in
+/// order of name, so the same textures always give the same atlas regardless of the order they are
+/// passed in.
+pub fn pack_textures(mut textures: Vec<(String, RgbaImage)>, padding: u32) -> TextureAtlas {
+    textures.sort_by(|a, b| b.1.height().cmp(&a.1.height()).then_with(|| a.0.cmp(&b.0)));
+
+    let area: u32 = textures
+        .iter()
+        .map(|&(_, ref texture)| (texture.width() + padding * 2) * (texture.height() + padding * 2))
+        .sum();
+    let widest = textures
+        .iter()
+        .map(|&(_, ref texture)| texture.width() + padding * 2)
+        .max()
+        .unwrap_or(1);
+    let width = u32::max(widest, f64::sqrt(area as f64).ceil() as u32).next_power_of_two();
+
+    // positions of the padded cells, row by row
+    let mut positions = Vec::with_capacity(textures.len());
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for &(_, ref texture) in &textures {
+        let cell_width = texture.width() + padding * 2;
+        let cell_height = texture.height() + padding * 2;
+
+        if x + cell_width > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+
+        positions.push((x, y));
+        x += cell_width;
+        row_height = u32::max(row_height, cell_height);
+    }
+
+    let height = u32::max(1, y + row_height).next_power_of_two();
+    let mut image = RgbaImage::new(width, height);
+    let mut rects = HashMap::new();
+
+    for ((name, texture), (x, y)) in textures.into_iter().zip(positions) {
+        copy_padded(&texture, &mut image, x, y, padding);
+
+        rects.insert(
+            name,
+            [
+                (x + padding) as f32 / width as f32,
+                1.0 - (y + padding) as f32 / height as f32,
+                texture.width() as f32 / width as f32,
+                -(texture.height() as f32) / height as f32,
+            ],
+        );
+    }
+
+    TextureAtlas {
+        image: image,
+        rects: rects,
+    }
+}
+
+/// Loads every PNG in a directory, named after its file without the extension. An image that can't be
+/// decoded is an `InvalidData` error.
+pub fn load_textures(directory: &str) -> io::Result<Vec<(String, RgbaImage)>> {
+    let mut textures = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |extension| extension != "png") {
+            continue;
+        }
+
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let texture = image::open(&path)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), error)))?;
+        textures.push((name, texture.to_rgba()));
+    }
+
+    Ok(textures)
+}
+
+/// Builds the atlas of the block textures, along with the rectangles of every block face.
+pub fn build_block_atlas(blocks: &Blocks) -> Result<(TextureAtlas, BlockTiles), BlockRegistryError> {
+    let textures = load_textures(BLOCK_TEXTURES_DIRECTORY)?;
+    // half a texture of padding keeps every mipmap level down to 2×2 pixels per texture clean
+    let padding = textures
+        .iter()
+        .map(|&(_, ref texture)| u32::max(texture.width(), texture.height()) / 2)
+        .max()
+        .unwrap_or(0);
+
+    let atlas = pack_textures(textures, padding);
+    let tiles = atlas.get_block_tiles(blocks)?;
+
+    Ok((atlas, tiles))
+}
+
+/// Copies a texture with its top left corner at `(x + padding, y + padding)` and extends its
+/// edge pixels outwards by `padding` pixels.
+fn copy_padded(source: &RgbaImage, target: &mut RgbaImage, x: u32, y: u32, padding: u32) {
+    let (width, height) = source.dimensions();
+
+    for target_y in 0..height + padding * 2 {
+        for target_x in 0..width + padding * 2 {
+            let source_x = u32::min(width - 1, target_x.saturating_sub(padding));
+            let source_y = u32::min(height - 1, target_y.saturating_sub(padding));
+            target.put_pixel(x + target_x, y + target_y, *source.get_pixel(source_x, source_y));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Textures of a few sizes, every pixel different so that misplaced copies show up.
+    fn create_textures() -> Vec<(String, RgbaImage)> {
+        [("a", 16, 16), ("b", 8, 8), ("c", 16, 32), ("d", 4, 4), ("e", 8, 8)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(name, width, height))| {
+                let texture = RgbaImage::from_fn(width, height, |x, y| Rgba([i as u8, x as u8, y as u8, 255]));
+                (name.to_string(), texture)
+            })
+            .collect()
+    }
+
+    /// The texture's rectangle in pixels as `(x, y, width, height)`, with `y` going down the image.
+    fn get_pixel_rect(atlas: &TextureAtlas, name: &str) -> (u32, u32, u32, u32) {
+        let rect = atlas.get_rect(name).unwrap();
+        let (width, height) = (atlas.image.width() as f32, atlas.image.height() as f32);
+        (
+            (rect[0] * width).round() as u32,
+            ((1.0 - rect[1]) * height).round() as u32,
+            (rect[2] * width).round() as u32,
+            (-rect[3] * height).round() as u32,
+        )
+    }
+
+    #[test]
+    fn test_no_overlap() {
+        let padding = 2;
+        let atlas = pack_textures(create_textures(), padding);
+        let cells: Vec<_> = create_textures()
+            .iter()
+            .map(|&(ref name, _)| {
+                let (x, y, width, height) = get_pixel_rect(&atlas, name);
+                (x - padding, y - padding, x + width + padding, y + height + padding)
+            })
+            .collect();
+
+        for (i, a) in cells.iter().enumerate() {
+            assert!(a.2 <= atlas.image.width() && a.3 <= atlas.image.height());
+            for b in &cells[i + 1..] {
+                assert!(a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_padding_copies_edges() {
+        let padding = 3;
+        let atlas = pack_textures(create_textures(), padding);
+
+        for &(ref name, ref texture) in &create_textures() {
+            let (x, y, width, height) = get_pixel_rect(&atlas, name);
+            assert_eq!((width, height), texture.dimensions());
+
+            for offset_y in 0..height + padding * 2 {
+                for offset_x in 0..width + padding * 2 {
+                    let source_x = u32::min(width - 1, offset_x.saturating_sub(padding));
+                    let source_y = u32::min(height - 1, offset_y.saturating_sub(padding));
+                    assert_eq!(
+                        atlas.image.get_pixel(x - padding + offset_x, y - padding + offset_y),
+                        texture.get_pixel(source_x, source_y)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_layout_for_same_textures() {
+        let atlas = pack_textures(create_textures(), 1);
+        let mut reversed = create_textures();
+        reversed.reverse();
+        let other = pack_textures(reversed, 1);
+
+        for &(ref name, _) in &create_textures() {
+            assert_eq!(atlas.get_rect(name), other.get_rect(name));
+        }
+        assert_eq!(atlas.image.dimensions(), other.image.dimensions());
+        assert!(atlas.image.pixels().eq(other.image.pixels()));
+    }
+}
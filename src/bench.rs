@@ -0,0 +1,83 @@
+use atlas::build_block_atlas;
+use chunk::SECTION_COUNT;
+use game::{create_world, Blocks, World, BLOCKS_FILE};
+use lighting::light_chunk;
+use mesher::*;
+use std::time::Instant;
+use worldgen::{DefaultTerrainGenerator, TerrainGenerator};
+
+const BENCHMARK_SEED: u64 = 1;
+const BENCHMARK_RADIUS: i32 = 4;
+
+/// Meshes the same patch of generated terrain with every meshing mode and level of detail and
+/// prints how many triangles each one produced and how long it took. Run with `cargo run --release -- --bench-meshing`.
+pub fn run_meshing_benchmark() {
+    let blocks = Blocks::load(BLOCKS_FILE).unwrap();
+    let (_, tiles) = build_block_atlas(&blocks).unwrap();
+    let appearance = BlockAppearance::new(&blocks, tiles);
+    let generator = DefaultTerrainGenerator::new(BENCHMARK_SEED);
+    let mut world = create_world(BENCHMARK_RADIUS as u8, &blocks);
+
+    for chunk_x in -BENCHMARK_RADIUS..BENCHMARK_RADIUS {
+        for chunk_z in -BENCHMARK_RADIUS..BENCHMARK_RADIUS {
+            let mut chunk = generator.generate_chunk(chunk_x, chunk_z);
+            light_chunk(&mut chunk, &world.get_light_properties());
+            world.insert_chunk(chunk_x, chunk_z, chunk);
+        }
+    }
+
+    // the chunks on the edge are decorated too, their features are cut off where the patch ends
+    for chunk_x in -BENCHMARK_RADIUS..BENCHMARK_RADIUS {
+        for chunk_z in -BENCHMARK_RADIUS..BENCHMARK_RADIUS {
+            generator.decorate(&mut world, chunk_x, chunk_z);
+        }
+    }
+    world.finish_features();
+
+    let memory_usage: usize = world
+        .chunks
+        .values()
+        .flat_map(|chunk| (0..SECTION_COUNT).filter_map(move |index| chunk.get_section(index)))
+        .map(|section| section.get_memory_usage())
+        .sum();
+    println!("Meshing {} chunks, whose blocks take {} KiB", world.chunks.len(), memory_usage / 1024);
+
+    for mode in &[MeshingMode::Naive, MeshingMode::Greedy] {
+        run_benchmark(&format!("{:?}", mode), &world, |neighborhood, chunk_x, chunk_z| {
+            build_chunk_mesh(neighborhood, chunk_x, chunk_z, &appearance, *mode)
+        });
+    }
+
+    for level in 1..MAX_DETAIL_LEVEL + 1 {
+        run_benchmark(&format!("Detail level {}", level), &world, |neighborhood, chunk_x, chunk_z| {
+            build_lod_mesh(neighborhood, chunk_x, chunk_z, &appearance, level)
+        });
+    }
+}
+
+fn run_benchmark<F>(name: &str, world: &World, build_mesh: F)
+where
+    F: Fn(&ChunkNeighborhood, i32, i32) -> ChunkMeshes,
+{
+    let start = Instant::now();
+    let mut triangles = 0;
+    let mut vertices = 0;
+
+    for &(chunk_x, chunk_z) in world.chunks.keys() {
+        let neighborhood = ChunkNeighborhood::from_world(world, chunk_x, chunk_z).unwrap();
+        let mesh = build_mesh(&neighborhood, chunk_x, chunk_z);
+
+        triangles += mesh.get_triangle_count();
+        vertices += mesh.get_vertex_count();
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "{}: {} triangles, {} vertices in {}.{:03} ms",
+        name,
+        triangles,
+        vertices,
+        elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000,
+        elapsed.subsec_nanos() / 1000 % 1000
+    );
+}
@@ -0,0 +1,132 @@
+use game::BlockType;
+use worldgen::{hash_seed, OctaveNoise};
+
+const CLIMATE_FREQUENCY: f64 = 1.0 / 512.0;
+const CLIMATE_OCTAVES: usize = 3;
+// how quickly a biome's influence falls off with its distance in climate space; lower values give wider borders
+const BLEND_SHARPNESS: f64 = 60.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains = 0,
+    Desert = 1,
+    Mountains = 2,
+    Ocean = 3,
+}
+
+pub const BIOMES: [Biome; 4] = [Biome::Plains, Biome::Desert, Biome::Mountains, Biome::Ocean];
+
+pub struct BiomeProperties {
+    pub base_height: f64,
+    /// How far the terrain's height can stray from `base_height`.
+    pub height_scale: f64,
+    pub surface_block: u16,
+    pub filler_block: u16,
+    /// The climate this biome is most likely to be picked at, both from 0 to 1.
+    pub temperature: f64,
+    pub humidity: f64,
+}
+
+impl Biome {
+    pub fn from_id(id: u8) -> Biome {
+        BIOMES[id as usize]
+    }
+
+    pub fn get_properties(&self) -> BiomeProperties {
+        match *self {
+            Biome::Plains => BiomeProperties {
+                base_height: 66.0,
+                height_scale: 6.0,
+                surface_block: BlockType::Grass as u16,
+                filler_block: BlockType::Dirt as u16,
+                temperature: 0.5,
+                humidity: 0.5,
+            },
+            Biome::Desert => BiomeProperties {
+                base_height: 66.0,
+                height_scale: 4.0,
+                surface_block: BlockType::Sand as u16,
+                filler_block: BlockType::Sand as u16,
+                temperature: 0.85,
+                humidity: 0.2,
+            },
+            Biome::Mountains => BiomeProperties {
+                base_height: 84.0,
+                height_scale: 32.0,
+                surface_block: BlockType::Stone as u16,
+                filler_block: BlockType::Stone as u16,
+                temperature: 0.2,
+                humidity: 0.35,
+            },
+            Biome::Ocean => BiomeProperties {
+                base_height: 46.0,
+                height_scale: 6.0,
+                surface_block: BlockType::Sand as u16,
+                filler_block: BlockType::Sand as u16,
+                temperature: 0.5,
+                humidity: 0.85,
+            },
+        }
+    }
+}
+
+/// Picks biomes from temperature and humidity noise.
+pub struct BiomeSource {
+    temperature_noise: OctaveNoise,
+    humidity_noise: OctaveNoise,
+}
+
+impl BiomeSource {
+    pub fn new(seed: u64) -> BiomeSource {
+        BiomeSource {
+            temperature_noise: OctaveNoise::new(hash_seed(seed, 1), CLIMATE_OCTAVES, CLIMATE_FREQUENCY),
+            humidity_noise: OctaveNoise::new(hash_seed(seed, 2), CLIMATE_OCTAVES, CLIMATE_FREQUENCY),
+        }
+    }
+
+    /// The temperature and humidity of a column, both from 0 to 1.
+    pub fn get_climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let temperature = self.temperature_noise.get(x as f64, z as f64);
+        let humidity = self.humidity_noise.get(x as f64, z as f64);
+
+        // octave noise rarely strays far from 0, so it's stretched to make the extremes reachable
+        (
+            f64::max(0.0, f64::min(1.0, temperature * 1.5 + 0.5)),
+            f64::max(0.0, f64::min(1.0, humidity * 1.5 + 0.5)),
+        )
+    }
+
+    /// How much each biome of `BIOMES` contributes to a column. The weights add up to 1 and change
+    /// smoothly from column to column, so they can be used to blend the biomes at their borders.
+    pub fn get_weights(&self, x: i32, z: i32) -> [f64; 4] {
+        let (temperature, humidity) = self.get_climate(x, z);
+        let mut weights = [0.0; 4];
+        let mut total = 0.0;
+
+        for (i, biome) in BIOMES.iter().enumerate() {
+            let properties = biome.get_properties();
+            let dt = temperature - properties.temperature;
+            let dh = humidity - properties.humidity;
+
+            weights[i] = f64::exp(-(dt * dt + dh * dh) * BLEND_SHARPNESS);
+            total += weights[i];
+        }
+
+        for weight in &mut weights {
+            *weight /= total;
+        }
+
+        weights
+    }
+}
+
+pub fn get_dominant_biome(weights: &[f64; 4]) -> Biome {
+    let mut dominant = 0;
+    for i in 1..weights.len() {
+        if weights[i] > weights[dominant] {
+            dominant = i;
+        }
+    }
+
+    BIOMES[dominant]
+}
@@ -0,0 +1,301 @@
+use game::{Block, BlockType, RenderLayer, BLOCK_TYPES};
+use lighting::MAX_LIGHT;
+use serde_json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+const FACE_NAMES: [&str; 6] = ["back", "top", "front", "bottom", "right", "left"];
+
+/// A block as written in the block definition file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDefinition {
+    /// The id saved in chunks. It is given explicitly so that reordering the file doesn't change what
+    /// saved worlds contain.
+    id: u16,
+    name: String,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    /// Whether the faces of neighbouring blocks show through this one. Defaults to true for blocks
+    /// that aren't in the opaque render layer.
+    transparent: Option<bool>,
+    /// "opaque", "cutout" or "translucent".
+    layer: Option<String>,
+    #[serde(default)]
+    hardness: f32,
+    /// The name of the block that is dropped when this one is broken, the block itself if not given.
+    drop: Option<String>,
+    #[serde(default)]
+    light: u8,
+    textures: Option<TextureDefinition>,
+}
+
+/// The textures of a block's faces. More specific keys win, so `{ "all": "a", "top": "b" }` uses
+/// `b` for the top and `a` for every other face.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDefinition {
+    all: Option<String>,
+    /// The four faces around the block: back, front, right and left.
+    side: Option<String>,
+    back: Option<String>,
+    top: Option<String>,
+    front: Option<String>,
+    bottom: Option<String>,
+    right: Option<String>,
+    left: Option<String>,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+impl TextureDefinition {
+    fn get_face(&self, face: usize) -> Option<&String> {
+        let specific = match face {
+            0 => &self.back,
+            1 => &self.top,
+            2 => &self.front,
+            3 => &self.bottom,
+            4 => &self.right,
+            _ => &self.left,
+        };
+        let side = if face == 1 || face == 3 { &None } else { &self.side };
+
+        specific.as_ref().or(side.as_ref()).or(self.all.as_ref())
+    }
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    DuplicateName(String),
+    DuplicateId { id: u16, first: String, second: String },
+    UnknownDrop { block: String, drop: String },
+    MissingTexture { block: String, face: &'static str },
+    UnknownTexture { block: String, texture: String },
+    InvalidHardness { block: String, hardness: f32 },
+    InvalidLight { block: String, light: u8 },
+    UnknownRenderLayer { block: String, layer: String },
+    /// A block the game refers to through `BlockType` is missing or defined at the wrong id.
+    BuiltinMismatch { name: &'static str, expected_id: u16 },
+}
+
+impl fmt::Display for BlockRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlockRegistryError::Io(ref error) => write!(f, "couldn't read the block definitions or textures: {}", error),
+            BlockRegistryError::Parse(ref error) => write!(f, "invalid block definitions: {}", error),
+            BlockRegistryError::DuplicateName(ref name) => write!(f, "block \"{}\" is defined more than once", name),
+            BlockRegistryError::DuplicateId { id, ref first, ref second } => {
+                write!(f, "blocks \"{}\" and \"{}\" both have id {}", first, second, id)
+            }
+            BlockRegistryError::UnknownDrop { ref block, ref drop } => {
+                write!(f, "block \"{}\" drops \"{}\", which isn't defined", block, drop)
+            }
+            BlockRegistryError::MissingTexture { ref block, face } => {
+                write!(f, "block \"{}\" has no texture for its {} face", block, face)
+            }
+            BlockRegistryError::UnknownTexture { ref block, ref texture } => {
+                write!(f, "block \"{}\" uses texture \"{}\", which doesn't exist", block, texture)
+            }
+            BlockRegistryError::InvalidHardness { ref block, hardness } => {
+                write!(f, "block \"{}\" has invalid hardness {}", block, hardness)
+            }
+            BlockRegistryError::InvalidLight { ref block, light } => write!(
+                f,
+                "block \"{}\" emits light level {}, the maximum is {}",
+                block, light, MAX_LIGHT
+            ),
+            BlockRegistryError::UnknownRenderLayer { ref block, ref layer } => write!(
+                f,
+                "block \"{}\" has render layer \"{}\", expected \"opaque\", \"cutout\" or \"translucent\"",
+                block, layer
+            ),
+            BlockRegistryError::BuiltinMismatch { name, expected_id } => {
+                write!(f, "block \"{}\" must be defined with id {}", name, expected_id)
+            }
+        }
+    }
+}
+
+impl Error for BlockRegistryError {}
+
+impl From<io::Error> for BlockRegistryError {
+    fn from(error: io::Error) -> BlockRegistryError {
+        BlockRegistryError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for BlockRegistryError {
+    fn from(error: serde_json::Error) -> BlockRegistryError {
+        BlockRegistryError::Parse(error)
+    }
+}
+
+/// Parses and validates a JSON array of block definitions. Every block is returned at the index of
+/// its id, whatever order they are written in. Ids may have gaps, so that removing a block doesn't
+/// change the ids of the others, and there is no block at those.
+pub fn parse_blocks(json: &str) -> Result<Vec<Option<Block>>, BlockRegistryError> {
+    let definitions: Vec<BlockDefinition> = serde_json::from_str(json)?;
+
+    let mut ids = HashMap::new();
+    let mut names = HashMap::new();
+    for definition in &definitions {
+        if let Some(first) = names.insert(definition.id, definition.name.clone()) {
+            return Err(BlockRegistryError::DuplicateId {
+                id: definition.id,
+                first: first,
+                second: definition.name.clone(),
+            });
+        }
+        if ids.insert(definition.name.clone(), definition.id).is_some() {
+            return Err(BlockRegistryError::DuplicateName(definition.name.clone()));
+        }
+    }
+
+    let count = definitions.iter().map(|definition| definition.id as usize + 1).max().unwrap_or(0);
+    let mut blocks: Vec<Option<Block>> = (0..count).map(|_| None).collect();
+    for definition in definitions {
+        let id = definition.id;
+        blocks[id as usize] = Some(create_block(id, definition, &ids)?);
+    }
+
+    for block_type in BLOCK_TYPES.iter() {
+        let expected_id = *block_type as u16;
+        let name = blocks
+            .get(expected_id as usize)
+            .and_then(|block| block.as_ref())
+            .map(|block| block.name.as_str());
+        if name != Some(block_type.get_name()) {
+            return Err(BlockRegistryError::BuiltinMismatch {
+                name: block_type.get_name(),
+                expected_id: expected_id,
+            });
+        }
+    }
+
+    Ok(blocks)
+}
+
+fn create_block(id: u16, definition: BlockDefinition, ids: &HashMap<String, u16>) -> Result<Block, BlockRegistryError> {
+    let drop_id = match definition.drop {
+        Some(ref drop) => *ids.get(drop).ok_or_else(|| BlockRegistryError::UnknownDrop {
+            block: definition.name.clone(),
+            drop: drop.clone(),
+        })?,
+        None => id,
+    };
+
+    if !definition.hardness.is_finite() || definition.hardness < 0.0 {
+        return Err(BlockRegistryError::InvalidHardness {
+            block: definition.name,
+            hardness: definition.hardness,
+        });
+    }
+
+    if definition.light > MAX_LIGHT {
+        return Err(BlockRegistryError::InvalidLight {
+            block: definition.name,
+            light: definition.light,
+        });
+    }
+
+    let layer = match definition.layer.as_ref().map(|layer| layer.as_str()) {
+        None | Some("opaque") => RenderLayer::Opaque,
+        Some("cutout") => RenderLayer::Cutout,
+        Some("translucent") => RenderLayer::Translucent,
+        Some(layer) => {
+            return Err(BlockRegistryError::UnknownRenderLayer {
+                block: definition.name.clone(),
+                layer: layer.to_string(),
+            })
+        }
+    };
+
+    // air is never drawn, so it doesn't need textures
+    let mut textures = Vec::new();
+    if id != BlockType::Air as u16 {
+        for (face, face_name) in FACE_NAMES.iter().enumerate() {
+            match definition.textures.as_ref().and_then(|textures| textures.get_face(face)) {
+                Some(texture) => textures.push(texture.clone()),
+                None => {
+                    return Err(BlockRegistryError::MissingTexture {
+                        block: definition.name,
+                        face: face_name,
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(Block {
+        id: id,
+        name: definition.name,
+        drop_id: drop_id,
+        solid: definition.solid,
+        transparent: definition.transparent.unwrap_or(layer != RenderLayer::Opaque),
+        layer: layer,
+        hardness: definition.hardness,
+        light: definition.light,
+        textures: textures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn load_definitions() -> Vec<Value> {
+        serde_json::from_str(include_str!("../data/blocks.json")).unwrap()
+    }
+
+    fn parse(definitions: &[Value]) -> Result<Vec<Option<Block>>, BlockRegistryError> {
+        parse_blocks(&serde_json::to_string(definitions).unwrap())
+    }
+
+    #[test]
+    fn test_order_in_file_does_not_change_ids() {
+        let mut definitions = load_definitions();
+        let blocks = parse(&definitions).unwrap();
+        definitions.reverse();
+        let reversed = parse(&definitions).unwrap();
+
+        assert_eq!(blocks.len(), reversed.len());
+        for (block, other) in blocks.iter().zip(reversed.iter()) {
+            let (block, other) = (block.as_ref().unwrap(), other.as_ref().unwrap());
+            assert_eq!(block.id, other.id);
+            assert_eq!(block.name, other.name);
+            assert_eq!(block.drop_id, other.drop_id);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_id() {
+        let mut definitions = load_definitions();
+        definitions[2]["id"] = Value::from(1);
+        match parse(&definitions) {
+            Err(BlockRegistryError::DuplicateId { id: 1, .. }) => {}
+            _ => panic!("expected a duplicate id error"),
+        }
+    }
+
+    #[test]
+    fn test_removed_block_keeps_other_ids() {
+        let mut definitions = load_definitions();
+        let glass = definitions.iter().position(|definition| definition["name"] == "glass").unwrap();
+        let glass_id = definitions[glass]["id"].as_u64().unwrap() as usize;
+        definitions.remove(glass);
+        let blocks = parse(&definitions).unwrap();
+
+        assert!(blocks[glass_id].is_none());
+        for block in blocks.iter().filter_map(|block| block.as_ref()) {
+            let definition = definitions.iter().find(|definition| definition["name"] == block.name.as_str()).unwrap();
+            assert_eq!(definition["id"].as_u64(), Some(block.id as u64));
+        }
+    }
+}
@@ -84,15 +84,16 @@ impl Camera {
 		let mut last = None;
 
 		for _ in 0..100 {
-			if game.world.is_in_rendered_world_bounds(f32::round(jump[0]) as i64, f32::round(jump[1]) as i16, f32::round(jump[2]) as i64) {
-				let ux = f32::round(jump[0]) as u32;
-				let uy = f32::round(jump[1]) as u8;
-				let uz = f32::round(jump[2]) as u32;
-				let id = game.world.get_block_id(ux, uy, uz);
-				if id != 0 && game.world.is_visible(f32::round(jump[0]) as u32, f32::round(jump[1]) as u8, f32::round(jump[2]) as u32) {
-					return (Some(game::BlockPos::new(ux, uy, uz, id)), last);
+			let x = f32::round(jump[0]) as i32;
+			let y = f32::round(jump[1]) as i32;
+			let z = f32::round(jump[2]) as i32;
+
+			if game.world.is_loaded(x, y, z) {
+				let id = game.world.get_block_id(x, y, z);
+				if id != 0 && game.world.is_visible(x, y, z) {
+					return (Some(game::BlockPos::new(x, y as u8, z, id)), last);
 				} else {
-					last = Some(game::BlockPos::new(ux, uy, uz, id));
+					last = Some(game::BlockPos::new(x, y as u8, z, id));
 				}
 			}
 			
@@ -0,0 +1,394 @@
+use bytebuffer::ByteBuffer;
+use std::io;
+
+/// The number of blocks along each side of a section.
+pub const SECTION_SIZE: usize = 16;
+/// The number of sections stacked on top of each other in a chunk.
+pub const SECTION_COUNT: usize = 16;
+const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+
+/// A block id together with the block's state. What the state means depends on the block, e.g. its
+/// orientation, growth stage or water level.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BlockData {
+    pub id: u16,
+    pub state: u16,
+}
+
+impl BlockData {
+    pub fn new(id: u16, state: u16) -> BlockData {
+        BlockData { id: id, state: state }
+    }
+
+    pub fn air() -> BlockData {
+        BlockData::new(0, 0)
+    }
+
+    pub fn is_air(&self) -> bool {
+        self.id == 0
+    }
+}
+
+/// A 16×16×16 cube of blocks. Every block is stored as an index into a palette of the distinct
+/// blocks (id and state) in the section, using as few bits per index as the palette size allows.
+/// Indices never cross a word boundary, so a section with 5 bit indices fits 12 of them in every `u64`.
+#[derive(Clone)]
+pub struct ChunkSection {
+    palette: Vec<BlockData>,
+    bits_per_block: usize,
+    data: Vec<u64>,
+    /// The number of blocks in the section that aren't air.
+    block_count: usize,
+}
+
+impl ChunkSection {
+    /// A section filled with a single block.
+    pub fn new(block: BlockData) -> ChunkSection {
+        ChunkSection {
+            palette: vec![block],
+            bits_per_block: 0,
+            data: Vec::new(),
+            block_count: if block.is_air() { 0 } else { SECTION_VOLUME },
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockData {
+        self.palette[self.get_index(get_block_index(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockData) {
+        let block_index = get_block_index(x, y, z);
+        let previous = self.palette[self.get_index(block_index)];
+        if previous == block {
+            return;
+        }
+
+        if previous.is_air() {
+            self.block_count += 1;
+        } else if block.is_air() {
+            self.block_count -= 1;
+        }
+
+        let palette_index = self.get_or_insert_palette_index(block);
+        self.set_index(block_index, palette_index);
+    }
+
+    /// Whether the section may contain the block. Blocks that were replaced keep their palette
+    /// entry, so this can be true for blocks that aren't there anymore.
+    pub fn may_contain(&self, block: u16) -> bool {
+        self.palette.iter().any(|entry| entry.id == block)
+    }
+
+    /// Whether every block in the section is air.
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+
+    /// The approximate number of bytes used by the section, for debugging memory use.
+    pub fn get_memory_usage(&self) -> usize {
+        self.palette.len() * 4 + self.data.len() * 8
+    }
+
+    /// Writes the section to a save file buffer.
+    pub fn to_buffer(&self, buf: &mut ByteBuffer) {
+        buf.write_u16(self.palette.len() as u16);
+        for entry in &self.palette {
+            buf.write_u16(entry.id);
+            buf.write_u16(entry.state);
+        }
+
+        buf.write_u8(self.bits_per_block as u8);
+        for word in &self.data {
+            buf.write_u64(*word);
+        }
+    }
+
+    /// Reads a section written by `to_buffer`, failing instead of panicking if the data is cut off
+    /// or doesn't make sense, including blocks for which `is_defined` is false.
+    pub fn from_buffer(buf: &mut ByteBuffer, is_defined: &Fn(u16) -> bool) -> io::Result<ChunkSection> {
+        check_remaining(buf, 2)?;
+        let palette_size = buf.read_u16() as usize;
+        if palette_size == 0 {
+            return Err(invalid_data("section has an empty palette".to_string()));
+        }
+
+        check_remaining(buf, palette_size * 4 + 1)?;
+        let palette: Vec<BlockData> = (0..palette_size)
+            .map(|_| {
+                let id = buf.read_u16();
+                BlockData::new(id, buf.read_u16())
+            })
+            .collect();
+
+        if let Some(block) = palette.iter().find(|block| !is_defined(block.id)) {
+            return Err(invalid_data(format!("section has unknown block {}", block.id)));
+        }
+
+        let bits_per_block = buf.read_u8() as usize;
+        if bits_per_block > 16 || palette_size > 1 << bits_per_block {
+            return Err(invalid_data(format!(
+                "section has {} bits per block for a palette of {} entries",
+                bits_per_block, palette_size
+            )));
+        }
+
+        let word_count = if bits_per_block == 0 {
+            0
+        } else {
+            let per_word = 64 / bits_per_block;
+            (SECTION_VOLUME + per_word - 1) / per_word
+        };
+
+        check_remaining(buf, word_count * 8)?;
+        let mut section = ChunkSection {
+            palette: palette,
+            bits_per_block: bits_per_block,
+            data: (0..word_count).map(|_| buf.read_u64()).collect(),
+            block_count: 0,
+        };
+
+        for block_index in 0..SECTION_VOLUME {
+            match section.palette.get(section.get_index(block_index)) {
+                Some(block) if !block.is_air() => section.block_count += 1,
+                Some(_) => {}
+                None => return Err(invalid_data("section has a block outside of its palette".to_string())),
+            }
+        }
+
+        Ok(section)
+    }
+
+    fn get_index(&self, block_index: usize) -> usize {
+        if self.bits_per_block == 0 {
+            return 0;
+        }
+
+        let per_word = 64 / self.bits_per_block;
+        let word = self.data[block_index / per_word];
+        let shift = (block_index % per_word) * self.bits_per_block;
+
+        ((word >> shift) & ((1 << self.bits_per_block) - 1)) as usize
+    }
+
+    fn set_index(&mut self, block_index: usize, palette_index: usize) {
+        let per_word = 64 / self.bits_per_block;
+        let mask = (1u64 << self.bits_per_block) - 1;
+        let shift = (block_index % per_word) * self.bits_per_block;
+        let word = &mut self.data[block_index / per_word];
+
+        *word = (*word & !(mask << shift)) | ((palette_index as u64) << shift);
+    }
+
+    fn get_or_insert_palette_index(&mut self, block: BlockData) -> usize {
+        if let Some(index) = self.palette.iter().position(|&entry| entry == block) {
+            return index;
+        }
+
+        // before making the indices wider, get rid of the entries that aren't used anymore
+        if self.palette.len() >= 1 << self.bits_per_block {
+            self.remove_unused_entries();
+        }
+
+        if self.palette.len() >= 1 << self.bits_per_block {
+            let bits_per_block = get_bits_per_block(self.palette.len() + 1);
+            self.repack(bits_per_block);
+        }
+
+        self.palette.push(block);
+        self.palette.len() - 1
+    }
+
+    fn remove_unused_entries(&mut self) {
+        let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| self.get_index(i)).collect();
+        let mut used = vec![false; self.palette.len()];
+        for &index in &indices {
+            used[index] = true;
+        }
+
+        if used.iter().all(|&used| used) {
+            return;
+        }
+
+        let mut remapped = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (index, &entry) in self.palette.iter().enumerate() {
+            if used[index] {
+                remapped[index] = palette.len();
+                palette.push(entry);
+            }
+        }
+
+        self.palette = palette;
+        for (block_index, &index) in indices.iter().enumerate() {
+            self.set_index(block_index, remapped[index]);
+        }
+    }
+
+    fn repack(&mut self, bits_per_block: usize) {
+        let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| self.get_index(i)).collect();
+        let per_word = 64 / bits_per_block;
+
+        self.bits_per_block = bits_per_block;
+        self.data = vec![0; (SECTION_VOLUME + per_word - 1) / per_word];
+
+        for (block_index, &index) in indices.iter().enumerate() {
+            self.set_index(block_index, index);
+        }
+    }
+}
+
+fn get_block_index(x: usize, y: usize, z: usize) -> usize {
+    (y * SECTION_SIZE + z) * SECTION_SIZE + x
+}
+
+/// The smallest number of bits that can index a palette of the given size.
+fn get_bits_per_block(palette_size: usize) -> usize {
+    let mut bits = 1;
+    while 1 << bits < palette_size {
+        bits += 1;
+    }
+
+    bits
+}
+
+/// Fails if fewer than `count` bytes are left to read, since reading past the end of a buffer panics.
+pub fn check_remaining(buf: &ByteBuffer, count: usize) -> io::Result<()> {
+    if buf.len() - buf.get_rpos() < count {
+        Err(invalid_data("the data ends too early".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_section() -> ChunkSection {
+        let mut section = ChunkSection::new(BlockData::new(0, 0));
+        for i in 0..SECTION_SIZE {
+            section.set(i, i, 0, BlockData::new(1 + i as u16 % 3, 0));
+        }
+        section
+    }
+
+    fn to_bytes(section: &ChunkSection) -> Vec<u8> {
+        let mut buf = ByteBuffer::new();
+        section.to_buffer(&mut buf);
+        buf.to_bytes()
+    }
+
+    /// Reads a section with block ids up to 63 defined.
+    fn read(bytes: &[u8]) -> io::Result<ChunkSection> {
+        ChunkSection::from_buffer(&mut ByteBuffer::from_bytes(bytes), &|block| block < 64)
+    }
+
+    #[test]
+    fn test_air_section_is_small() {
+        let section = ChunkSection::new(BlockData::air());
+        assert!(section.is_empty());
+        assert!(section.get_memory_usage() <= 8);
+        assert_eq!(section.get(3, 9, 12), BlockData::air());
+    }
+
+    #[test]
+    fn test_palette_growth() {
+        // 40 distinct blocks need 6 bits per block
+        let block_at = |x: usize, y: usize, z: usize| BlockData::new(1 + ((x + y * 3 + z * 7) % 40) as u16, 0);
+        let mut section = ChunkSection::new(BlockData::air());
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    section.set(x, y, z, block_at(x, y, z));
+                }
+            }
+        }
+        assert_eq!(section.bits_per_block, 6);
+
+        let loaded = read(&to_bytes(&section)).unwrap();
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    assert_eq!(section.get(x, y, z), block_at(x, y, z));
+                    assert_eq!(loaded.get(x, y, z), block_at(x, y, z));
+                }
+            }
+        }
+        assert!(!loaded.is_empty());
+    }
+
+    #[test]
+    fn test_unused_entries_are_reused() {
+        let mut section = ChunkSection::new(BlockData::air());
+        section.set(0, 0, 0, BlockData::new(1, 0));
+        assert_eq!(section.bits_per_block, 1);
+
+        // air isn't used anymore, so its entry makes room for the new block instead of widening
+        // the indices
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    section.set(x, y, z, BlockData::new(1, 0));
+                }
+            }
+        }
+        section.set(5, 5, 5, BlockData::new(2, 0));
+
+        assert_eq!(section.bits_per_block, 1);
+        assert_eq!(section.palette.len(), 2);
+        assert_eq!(section.get(5, 5, 5), BlockData::new(2, 0));
+        assert_eq!(section.get(0, 0, 0), BlockData::new(1, 0));
+        assert_eq!(section.get(15, 15, 15), BlockData::new(1, 0));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let section = create_section();
+        let loaded = read(&to_bytes(&section)).unwrap();
+
+        for i in 0..SECTION_SIZE {
+            assert_eq!(loaded.get(i, i, 0), section.get(i, i, 0));
+            assert_eq!(loaded.get(i, 0, 1), section.get(i, 0, 1));
+        }
+        assert!(!loaded.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let bytes = to_bytes(&create_section());
+        for &length in &[0, 1, 5, bytes.len() - 1] {
+            assert!(read(&bytes[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_palette_too_large_for_bits() {
+        let mut bytes = to_bytes(&create_section());
+        // the bits per block follow the palette size and 4 palette entries of 4 bytes each
+        assert_eq!(bytes[2 + 4 * 4], 2);
+        bytes[2 + 4 * 4] = 1;
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_index_outside_palette() {
+        let mut section = ChunkSection::new(BlockData::new(0, 0));
+        section.set(0, 0, 0, BlockData::new(1, 0));
+        let mut bytes = to_bytes(&section);
+        // with 2 palette entries and 1 bit per block, remove the second entry and the bit still points to it
+        bytes.drain(6..10);
+        bytes[1] = 1;
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_unknown_block() {
+        let mut section = ChunkSection::new(BlockData::new(0, 0));
+        section.set(0, 0, 0, BlockData::new(64, 0));
+        assert!(read(&to_bytes(&section)).is_err());
+    }
+}
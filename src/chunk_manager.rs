@@ -0,0 +1,177 @@
+use game::{get_chunk_position, Chunk, World};
+use mesher::NEIGHBOR_CHUNK_OFFSETS;
+use nalgebra::Vector3;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use save::WorldSave;
+use workers::{Job, WorkerPool};
+use worldgen::TerrainGenerator;
+
+const DEFAULT_MAX_LOADS_PER_TICK: usize = 4;
+const DEFAULT_MAX_JOBS_IN_FLIGHT: usize = 16;
+
+/// Keeps the chunks around a position loaded, loading the nearest missing chunks first. Chunks are
+/// loaded from the save or generated on the worker pool, and added to the world once they come back.
+pub struct ChunkManager {
+    /// Chunks within this many chunks of the camera are loaded.
+    pub load_radius: i32,
+    /// Chunks further than this many chunks from the camera are unloaded. Should be larger than
+    /// `load_radius` so that chunks on the edge don't get reloaded when moving back and forth.
+    pub unload_radius: i32,
+    pub max_loads_per_tick: usize,
+    /// How many chunks may be waiting to be loaded at once.
+    pub max_jobs_in_flight: usize,
+    generator: Arc<TerrainGenerator>,
+    center: (i32, i32),
+    loading: HashSet<(i32, i32)>,
+    loaded: Vec<(i32, i32, Chunk)>,
+    /// Unloaded chunks are written here so that they don't have to be generated (and lose their edits) again.
+    save: WorldSave,
+    // chunks that couldn't be saved are kept in memory instead
+    unsaved_chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl ChunkManager {
+    pub fn new(load_radius: u8, generator: Arc<TerrainGenerator>, save: WorldSave) -> ChunkManager {
+        ChunkManager {
+            load_radius: load_radius as i32,
+            unload_radius: load_radius as i32 + 2,
+            max_loads_per_tick: DEFAULT_MAX_LOADS_PER_TICK,
+            max_jobs_in_flight: DEFAULT_MAX_JOBS_IN_FLIGHT,
+            generator: generator,
+            center: (0, 0),
+            loading: HashSet::new(),
+            loaded: Vec::new(),
+            save: save,
+            unsaved_chunks: HashMap::new(),
+        }
+    }
+
+    /// Hands over a chunk loaded or generated by the worker pool. It is added to the world on the next update.
+    pub fn receive_loaded_chunk(&mut self, chunk_x: i32, chunk_z: i32, chunk: Chunk) {
+        self.loading.remove(&(chunk_x, chunk_z));
+        self.loaded.push((chunk_x, chunk_z, chunk));
+    }
+
+    pub fn update(&mut self, world: &mut World, position: &Vector3<f32>, workers: &WorkerPool) {
+        let center = get_center_chunk(position);
+        self.center = center;
+
+        let to_unload: Vec<(i32, i32)> = world
+            .chunks
+            .keys()
+            .filter(|chunk| !self.is_in_unload_radius(**chunk))
+            .cloned()
+            .collect();
+
+        for (chunk_x, chunk_z) in to_unload {
+            if let Some(chunk) = world.remove_chunk(chunk_x, chunk_z) {
+                self.save_chunk(chunk_x, chunk_z, chunk);
+            }
+        }
+
+        for (chunk_x, chunk_z, chunk) in self.loaded.split_off(0) {
+            // the camera may have moved away while the chunk was being loaded; it is simply
+            // loaded again if the camera comes back
+            if !self.is_in_unload_radius((chunk_x, chunk_z)) || world.is_chunk_loaded(chunk_x, chunk_z) {
+                continue;
+            }
+
+            world.insert_chunk(chunk_x, chunk_z, chunk);
+            self.decorate_around(world, chunk_x, chunk_z);
+        }
+
+        for (chunk_x, chunk_z) in self.get_missing_chunks(world, center) {
+            // chunks that couldn't be saved still have their light from when they were loaded
+            if let Some(chunk) = self.unsaved_chunks.remove(&(chunk_x, chunk_z)) {
+                world.insert_chunk(chunk_x, chunk_z, chunk);
+                self.decorate_around(world, chunk_x, chunk_z);
+            } else if self.loading.len() < self.max_jobs_in_flight {
+                self.loading.insert((chunk_x, chunk_z));
+                workers.submit(Job::Load {
+                    chunk_x: chunk_x,
+                    chunk_z: chunk_z,
+                });
+            }
+        }
+    }
+
+    /// Decorates the chunks that a newly added chunk completed the neighbourhood of, which may include
+    /// the chunk itself. Decorating waits for all eight neighbours, so that features can reach into
+    /// them without ever having to wait for a chunk to be loaded.
+    fn decorate_around(&self, world: &mut World, chunk_x: i32, chunk_z: i32) {
+        for &(dx, dz) in [(0, 0)].iter().chain(NEIGHBOR_CHUNK_OFFSETS.iter()) {
+            let (chunk_x, chunk_z) = (chunk_x + dx, chunk_z + dz);
+            let ready = world.get_chunk(chunk_x, chunk_z).map_or(false, |chunk| !chunk.decorated)
+                && NEIGHBOR_CHUNK_OFFSETS
+                    .iter()
+                    .all(|&(dx, dz)| world.is_chunk_loaded(chunk_x + dx, chunk_z + dz));
+
+            if ready {
+                self.generator.decorate(world, chunk_x, chunk_z);
+                world.get_chunk_mut(chunk_x, chunk_z).unwrap().decorated = true;
+            }
+        }
+
+        world.finish_features();
+    }
+
+    /// Saves every loaded chunk, e.g. before quitting.
+    pub fn save_all(&mut self, world: &World) {
+        for (&(chunk_x, chunk_z), chunk) in &world.chunks {
+            self.save_chunk(chunk_x, chunk_z, (**chunk).clone());
+        }
+
+        for ((chunk_x, chunk_z), chunk) in self.unsaved_chunks.drain().collect::<Vec<_>>() {
+            self.save_chunk(chunk_x, chunk_z, chunk);
+        }
+    }
+
+    fn save_chunk(&mut self, chunk_x: i32, chunk_z: i32, chunk: Chunk) {
+        if let Err(error) = self.save.save_chunk(chunk_x, chunk_z, &chunk) {
+            println!("Failed to save chunk {}, {}: {}", chunk_x, chunk_z, error);
+            self.unsaved_chunks.insert((chunk_x, chunk_z), chunk);
+        }
+    }
+
+    fn is_in_unload_radius(&self, chunk: (i32, i32)) -> bool {
+        distance_squared(chunk, self.center) <= self.unload_radius * self.unload_radius
+    }
+
+    /// The chunks within the load radius that are neither loaded nor being loaded, nearest first
+    /// and at most `max_loads_per_tick` of them.
+    fn get_missing_chunks(&self, world: &World, center: (i32, i32)) -> Vec<(i32, i32)> {
+        let radius = self.load_radius;
+        let mut missing = Vec::new();
+
+        for chunk_x in center.0 - radius..center.0 + radius + 1 {
+            for chunk_z in center.1 - radius..center.1 + radius + 1 {
+                if distance_squared((chunk_x, chunk_z), center) <= radius * radius
+                    && !world.is_chunk_loaded(chunk_x, chunk_z)
+                    && !self.loading.contains(&(chunk_x, chunk_z))
+                {
+                    missing.push((chunk_x, chunk_z));
+                }
+            }
+        }
+
+        missing.sort_by_key(|chunk| distance_squared(*chunk, center));
+        missing.truncate(self.max_loads_per_tick);
+
+        missing
+    }
+}
+
+pub fn get_center_chunk(position: &Vector3<f32>) -> (i32, i32) {
+    let x = f32::floor(position[0] + 0.5) as i32;
+    let z = f32::floor(position[2] + 0.5) as i32;
+
+    get_chunk_position(x, z)
+}
+
+fn distance_squared(a: (i32, i32), b: (i32, i32)) -> i32 {
+    let dx = a.0 - b.0;
+    let dz = a.1 - b.1;
+
+    dx * dx + dz * dz
+}
@@ -0,0 +1,200 @@
+use chunk::BlockData;
+use game::{BlockType, World};
+
+/// The state of a fluid block that the fluid flows out of. It never dries up.
+pub const SOURCE_FLUID: u16 = 0;
+/// The lowest level of flowing fluid. Flowing fluid is 1 level lower than the fluid it flows out
+/// of for water and 2 levels lower for lava, so water spreads further.
+pub const MAX_FLUID_LEVEL: u16 = 7;
+/// Set in the state of fluid that is falling down, which is fed from above instead of the sides.
+pub const FALLING_FLUID: u16 = 8;
+
+// the sides that fluid spreads to, the ones around a block at the same height
+const HORIZONTAL_NEIGHBORS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    pub fn from_block(block: u16) -> Option<Fluid> {
+        if block == BlockType::Water as u16 {
+            Some(Fluid::Water)
+        } else if block == BlockType::Lava as u16 {
+            Some(Fluid::Lava)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_block(&self) -> u16 {
+        match *self {
+            Fluid::Water => BlockType::Water as u16,
+            Fluid::Lava => BlockType::Lava as u16,
+        }
+    }
+
+    /// How many ticks the fluid waits before it flows on.
+    pub fn get_tick_delay(&self) -> u64 {
+        match *self {
+            Fluid::Water => 5,
+            Fluid::Lava => 30,
+        }
+    }
+
+    fn get_level_drop(&self) -> u16 {
+        match *self {
+            Fluid::Water => 1,
+            Fluid::Lava => 2,
+        }
+    }
+}
+
+/// How far a fluid is from its source: 0 for source blocks and falling fluid, which flow out as
+/// if they were a source, otherwise the level stored in the state.
+fn get_level(state: u16) -> u16 {
+    if state & FALLING_FLUID != 0 {
+        0
+    } else {
+        state & MAX_FLUID_LEVEL
+    }
+}
+
+/// How high a fluid block is filled, from 0 to 1, when there isn't the same fluid above it.
+/// Sources and falling fluid are almost full and flowing fluid gets shallower with each level.
+pub fn get_fluid_height(state: u16) -> f32 {
+    (MAX_FLUID_LEVEL + 1 - get_level(state)) as f32 / (MAX_FLUID_LEVEL + 2) as f32
+}
+
+/// Runs the scheduled update of a fluid block: flowing fluid settles at the level the fluid around
+/// it feeds it, or dries up if nothing does, then the fluid flows down or, if it can't, out to
+/// the sides. Lava that touches water hardens instead.
+pub fn update_fluid(world: &mut World, x: i32, y: i32, z: i32) {
+    let block = world.get_block_data(x, y, z);
+    let fluid = match Fluid::from_block(block.id) {
+        Some(fluid) => fluid,
+        None => return,
+    };
+
+    if fluid == Fluid::Lava && harden_lava(world, x, y, z, block.state) {
+        return;
+    }
+
+    if block.state != SOURCE_FLUID {
+        match get_fed_state(world, fluid, x, y, z, block.state) {
+            // changing the block schedules it again, so it flows on once the new level had its delay
+            Some(state) if state != block.state => {
+                world.set_block_data(x, y, z, BlockData::new(block.id, state));
+                return;
+            }
+            Some(_) => {}
+            None => {
+                world.set_block_data(x, y, z, BlockData::air());
+                return;
+            }
+        }
+    }
+
+    if can_flow_into(world, fluid, x, y - 1, z, FALLING_FLUID) {
+        world.set_block_data(x, y - 1, z, BlockData::new(fluid.get_block(), FALLING_FLUID));
+        return;
+    }
+
+    // fluid that lands on the same fluid joins it instead of spreading over its surface
+    let below = world.get_block_id(x, y - 1, z);
+    let level = get_level(block.state) + fluid.get_level_drop();
+    if below == fluid.get_block() || level > MAX_FLUID_LEVEL {
+        return;
+    }
+
+    for &(dx, dz) in HORIZONTAL_NEIGHBORS.iter() {
+        if can_flow_into(world, fluid, x + dx, y, z + dz, level) {
+            world.set_block_data(x + dx, y, z + dz, BlockData::new(fluid.get_block(), level));
+        }
+    }
+}
+
+/// Whether updating a fluid block would change anything. Flowing fluid may dry up or flow on, but
+/// a source only does something if it can flow somewhere or is lava that touches water.
+pub fn needs_update(world: &World, x: i32, y: i32, z: i32) -> bool {
+    let block = world.get_block_data(x, y, z);
+    let fluid = match Fluid::from_block(block.id) {
+        Some(fluid) => fluid,
+        None => return false,
+    };
+
+    block.state != SOURCE_FLUID
+        || (fluid == Fluid::Lava && touches_water(world, x, y, z))
+        || can_flow_into(world, fluid, x, y - 1, z, FALLING_FLUID)
+        || HORIZONTAL_NEIGHBORS
+            .iter()
+            .any(|&(dx, dz)| can_flow_into(world, fluid, x + dx, y, z + dz, fluid.get_level_drop()))
+}
+
+/// The state that a block of flowing fluid is fed to by the fluid around it: falling if the same
+/// fluid is above it, otherwise one drop below the highest fluid next to it. `None` if it isn't
+/// fed at all, or not enough to reach it. A neighbour that isn't loaded may be feeding it, so
+/// the block keeps its current state until that neighbour is loaded.
+fn get_fed_state(world: &World, fluid: Fluid, x: i32, y: i32, z: i32, state: u16) -> Option<u16> {
+    if world.get_block_id(x, y + 1, z) == fluid.get_block() {
+        return Some(FALLING_FLUID);
+    }
+
+    if HORIZONTAL_NEIGHBORS
+        .iter()
+        .any(|&(dx, dz)| !world.is_loaded(x + dx, y, z + dz))
+    {
+        return Some(state);
+    }
+
+    HORIZONTAL_NEIGHBORS
+        .iter()
+        .map(|&(dx, dz)| world.get_block_data(x + dx, y, z + dz))
+        .filter(|neighbor| neighbor.id == fluid.get_block())
+        .map(|neighbor| get_level(neighbor.state) + fluid.get_level_drop())
+        .min()
+        .and_then(|level| if level <= MAX_FLUID_LEVEL { Some(level) } else { None })
+}
+
+/// Whether fluid in the given state can flow into a block: air, or the same fluid if it is
+/// flowing further from its source, so that it would rise.
+fn can_flow_into(world: &World, fluid: Fluid, x: i32, y: i32, z: i32, state: u16) -> bool {
+    if !world.is_loaded(x, y, z) {
+        return false;
+    }
+
+    let target = world.get_block_data(x, y, z);
+    if target.is_air() {
+        return true;
+    }
+
+    target.id == fluid.get_block()
+        && target.state != SOURCE_FLUID
+        && target.state & FALLING_FLUID == 0
+        && (state & FALLING_FLUID != 0 || get_level(target.state) > get_level(state))
+}
+
+/// Turns lava that touches water into stone if it is a source, or into cobblestone if it is
+/// flowing. Returns whether it did.
+fn harden_lava(world: &mut World, x: i32, y: i32, z: i32, state: u16) -> bool {
+    if !touches_water(world, x, y, z) {
+        return false;
+    }
+
+    let block = if state == SOURCE_FLUID {
+        BlockType::Stone
+    } else {
+        BlockType::Cobblestone
+    };
+    world.set_block_data(x, y, z, BlockData::new(block as u16, 0));
+
+    true
+}
+
+fn touches_water(world: &World, x: i32, y: i32, z: i32) -> bool {
+    [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)]
+        .iter()
+        .any(|&(dx, dy, dz)| world.get_block_id(x + dx, y + dy, z + dz) == BlockType::Water as u16)
+}
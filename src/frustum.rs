@@ -0,0 +1,152 @@
+use nalgebra::{Matrix4, Vector3};
+
+/// A plane of the points `p` for which `normal · p + distance` is 0. Points on the side the
+/// normal points to are in front of it.
+#[derive(Copy, Clone, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// The plane `a·x + b·y + c·z + d = 0`, scaled so that its normal has unit length.
+    fn new(a: f32, b: f32, c: f32, d: f32) -> Plane {
+        let normal = Vector3::new(a, b, c);
+        let length = normal.norm();
+
+        Plane {
+            normal: normal / length,
+            distance: d / length,
+        }
+    }
+
+    /// How far the point is in front of the plane, negative if it is behind it.
+    pub fn get_signed_distance(&self, point: &Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// The space that a camera can see, bounded by six planes that face inwards.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a matrix that takes world space to clip space, i.e. the
+    /// projection matrix times the view matrix. A point is visible if its clip space coordinates
+    /// are all between -w and w, and each of those six conditions is one plane in world space.
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Frustum {
+        let row = |index: usize| {
+            [
+                matrix[(index, 0)],
+                matrix[(index, 1)],
+                matrix[(index, 2)],
+                matrix[(index, 3)],
+            ]
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let plane = |sign: f32, row: [f32; 4]| {
+            Plane::new(
+                w[0] + sign * row[0],
+                w[1] + sign * row[1],
+                w[2] + sign * row[2],
+                w[3] + sign * row[3],
+            )
+        };
+
+        Frustum {
+            planes: [
+                plane(1.0, x),
+                plane(-1.0, x),
+                plane(1.0, y),
+                plane(-1.0, y),
+                plane(1.0, z),
+                plane(-1.0, z),
+            ],
+        }
+    }
+
+    /// Whether any part of the axis aligned box between `min` and `max` may be visible. Only the
+    /// corner of the box furthest in front of each plane is tested, so a box that is just outside
+    /// of a corner of the frustum can still count as visible, but a visible box never is culled.
+    pub fn intersects_box(&self, min: &Vector3<f32>, max: &Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = Vector3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+
+            plane.get_signed_distance(&corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::Camera;
+
+    /// The frustum of a camera at `position` looking down +z, the way the renderer builds it.
+    fn create_frustum(position: Vector3<f32>) -> Frustum {
+        let mut camera = Camera::new(90);
+        camera.position = position;
+        let projection = camera.create_projection_matrix((800, 600));
+        let view = camera.get_view_matrix().try_inverse().unwrap();
+
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    fn intersects(frustum: &Frustum, min: [f32; 3], max: [f32; 3]) -> bool {
+        frustum.intersects_box(
+            &Vector3::new(min[0], min[1], min[2]),
+            &Vector3::new(max[0], max[1], max[2]),
+        )
+    }
+
+    #[test]
+    fn test_box_in_front() {
+        let frustum = create_frustum(Vector3::new(0.0, 0.0, 0.0));
+        assert!(intersects(&frustum, [-1.0, -1.0, 9.0], [1.0, 1.0, 11.0]));
+        assert!(intersects(&frustum, [-1.0, -1.0, 500.0], [1.0, 1.0, 502.0]));
+    }
+
+    #[test]
+    fn test_box_behind() {
+        let frustum = create_frustum(Vector3::new(0.0, 0.0, 0.0));
+        assert!(!intersects(&frustum, [-1.0, -1.0, -11.0], [1.0, 1.0, -9.0]));
+    }
+
+    #[test]
+    fn test_box_to_the_side() {
+        let frustum = create_frustum(Vector3::new(0.0, 0.0, 0.0));
+        assert!(!intersects(&frustum, [100.0, -1.0, 9.0], [102.0, 1.0, 11.0]));
+        assert!(!intersects(&frustum, [-102.0, -1.0, 9.0], [-100.0, 1.0, 11.0]));
+        assert!(!intersects(&frustum, [-1.0, 100.0, 9.0], [1.0, 102.0, 11.0]));
+        assert!(!intersects(&frustum, [-1.0, -102.0, 9.0], [1.0, -100.0, 11.0]));
+    }
+
+    #[test]
+    fn test_box_beyond_far_plane() {
+        let frustum = create_frustum(Vector3::new(0.0, 0.0, 0.0));
+        assert!(!intersects(&frustum, [-1.0, -1.0, 2000.0], [1.0, 1.0, 2002.0]));
+    }
+
+    #[test]
+    fn test_box_straddling_a_plane() {
+        let frustum = create_frustum(Vector3::new(0.0, 0.0, 0.0));
+        // across the right side, and around the camera across the near plane
+        assert!(intersects(&frustum, [0.0, -1.0, 9.0], [100.0, 1.0, 11.0]));
+        assert!(intersects(&frustum, [-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_moved_camera() {
+        let frustum = create_frustum(Vector3::new(32.0, 80.0, 32.0));
+        assert!(intersects(&frustum, [31.0, 79.0, 40.0], [33.0, 81.0, 42.0]));
+        assert!(!intersects(&frustum, [-1.0, -1.0, 9.0], [1.0, 1.0, 11.0]));
+        assert!(!intersects(&frustum, [31.0, 79.0, 22.0], [33.0, 81.0, 24.0]));
+    }
+}
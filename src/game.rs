@@ -3,157 +3,469 @@ pub struct Game {
 }
 
 impl Game {
-	pub fn new(air_block: u8, render_distance: u8) -> Game {
+	pub fn new(render_distance: u8, blocks: &Blocks) -> Game {
 		Game {
-			world: create_world(air_block, render_distance as usize),
+			world: create_world(render_distance, blocks),
 		}
 	}
+
+	/// Updates the game by one tick, `time::TICKS_PER_SECOND` times per second.
+	pub fn tick(&mut self) {
+		self.world.tick();
+	}
 }
 
+use biome::{Biome, BIOMES};
+use block_registry::{parse_blocks, BlockRegistryError};
+use bytebuffer::ByteBuffer;
+use chunk::{check_remaining, invalid_data, BlockData, ChunkSection, SECTION_COUNT, SECTION_SIZE};
+use fluid::{self, Fluid};
+use lighting::{self, ChunkLight, LightProperties, LightType, MAX_LIGHT};
+use mesher::NEIGHBOR_CHUNK_OFFSETS;
+use worldgen::can_feature_replace;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use tick::{self, TickScheduler};
+use time::WorldTime;
+use vegetation;
 pub struct World {
-	pub chunks: Vec<Vec<Chunk>>,
+	/// The loaded chunks, shared with the mesh jobs of the worker pool. A chunk that a job still
+	/// holds is copied before it is changed.
+	pub chunks: HashMap<(i32, i32), Arc<Chunk>>,
 	pub render_distance: u8,
+	/// The blocks placed by features that haven't been lit yet, see `finish_features`.
+	feature_blocks: Vec<(i32, i32, i32)>,
+	light_properties: Arc<LightProperties>,
+	/// Whether the player collides with each block, by id.
+	solid_blocks: Vec<bool>,
+	pub time: WorldTime,
+	pub ticks: TickScheduler,
+}
+
+pub fn get_chunk_position(x: i32, z: i32) -> (i32, i32) {
+	(x >> 4, z >> 4)
 }
 
-use Instance;
 impl World {
-	pub fn get_block_id(&self, x: u32, y: u8, z: u32) -> u8 {
-		self.chunks[(x >> 4) as usize][(z >> 4) as usize].blocks[(x & 15) as usize]
-			[(z & 15) as usize][y as usize]
-	}
+	/// Runs the block updates that are due at this tick, then the random ticks of every section.
+	pub fn tick(&mut self) {
+		self.time.tick();
+
+		for tick in self.ticks.advance() {
+			// the block may have been replaced or unloaded since it asked for the update
+			if self.get_block_id(tick.x, tick.y, tick.z) != tick.block {
+				continue;
+			}
 
-	pub fn get_block<'a>(&self, blocks: &'a Blocks, x: u32, y: u8, z: u32) -> &'a Block {
-		blocks.get_block(self.get_block_id(x, y, z))
+			if Fluid::from_block(tick.block).is_some() {
+				fluid::update_fluid(self, tick.x, tick.y, tick.z);
+			}
+		}
+
+		self.run_random_ticks();
 	}
 
-	pub fn is_solid_block(&self, x: f32, y: f32, z: f32) -> bool {
-		self.is_in_rendered_world_bounds(x as i64, y as i16, z as i64)
-			&& self.get_block_id((x + 0.5) as u32, (y + 0.5) as u8, (z + 0.5) as u32) > 0
+	/// Asks for the block at the given position to be updated `delay` ticks from now, unless it
+	/// is replaced before then.
+	pub fn schedule_tick(&mut self, x: i32, y: i32, z: i32, delay: u64) {
+		let block = self.get_block_id(x, y, z);
+		self.ticks.schedule(x, y, z, block, delay);
 	}
 
-	pub fn set_block(&mut self, x: u32, y: u8, z: u32, block: &Block) {
-		self.set_block_ignore_neighbors(x, y, z, block.id);
+	fn run_random_ticks(&mut self) {
+		if self.ticks.random_tick_speed == 0 {
+			return;
+		}
 
-		for block_pos in &mut self.get_facial_neighbors(x as i64, y as i16, z as i64) {
-			if block_pos.block_id != 0 {
-				self.set_block_ignore_neighbors(
-					block_pos.x,
-					block_pos.y,
-					block_pos.z,
-					block_pos.block_id,
-				);
+		// sorted, so that the sections take their turns in the same order every time
+		let mut sections: Vec<(i32, i32, usize)> = self
+			.chunks
+			.iter()
+			.flat_map(|(&(chunk_x, chunk_z), chunk)| {
+				(0..SECTION_COUNT)
+					.filter(move |&section| chunk.get_section(section).is_some())
+					.map(move |section| (chunk_x, chunk_z, section))
+			})
+			.collect();
+		sections.sort();
+
+		for (chunk_x, chunk_z, section) in sections {
+			let mut random = self.ticks.get_section_random(chunk_x, chunk_z, section);
+
+			for _ in 0..self.ticks.random_tick_speed {
+				let (x, y, z) = tick::pick_random_block(&mut random, chunk_x, chunk_z, section);
+				let block = self.get_block_data(x, y, z);
+
+				if block.id == BlockType::Grass as u16 {
+					vegetation::tick_grass(self, &mut random, x, y, z);
+				} else if block.id == BlockType::Leaves as u16 {
+					vegetation::tick_leaves(self, x, y, z, block.state);
+				}
 			}
 		}
 	}
 
-	pub fn get_instance_vector(&mut self) -> Vec<Instance> {
-		let mut vec = Vec::new();
+	pub fn get_chunk(&self, chunk_x: i32, chunk_z: i32) -> Option<&Chunk> {
+		self.chunks.get(&(chunk_x, chunk_z)).map(|chunk| &**chunk)
+	}
+
+	pub fn get_chunk_mut(&mut self, chunk_x: i32, chunk_z: i32) -> Option<&mut Chunk> {
+		self.chunks.get_mut(&(chunk_x, chunk_z)).map(Arc::make_mut)
+	}
+
+	pub fn is_chunk_loaded(&self, chunk_x: i32, chunk_z: i32) -> bool {
+		self.chunks.contains_key(&(chunk_x, chunk_z))
+	}
+
+	/// Adds a chunk that was already lit with `lighting::light_chunk`, which is done on the worker
+	/// pool, and lets light flow between it and its neighbours.
+	pub fn insert_chunk(&mut self, chunk_x: i32, chunk_z: i32, mut chunk: Chunk) {
+		chunk.dirty = true;
+		self.chunks.insert((chunk_x, chunk_z), Arc::new(chunk));
+
+		let light_properties = self.light_properties.clone();
+		lighting::light_chunk_borders(self, &light_properties, chunk_x, chunk_z);
+		self.schedule_loaded_fluids(chunk_x, chunk_z);
 
-		for chunk_x in 0..self.chunks.len() {
-			for chunk_z in 0..self.chunks[chunk_x].len() {
-				for block in &self.chunks[chunk_x][chunk_z].visible_blocks {
-					let mut mat = utils::get_identity_matrix();
-					mat[(0, 3)] = block.x as f32;
-					mat[(1, 3)] = block.y as f32;
-					mat[(2, 3)] = block.z as f32;
+		self.mark_neighbors_dirty(chunk_x, chunk_z);
+	}
 
-					vec.push(Instance {
-						matrix: mat.into(),
-						id: block.block_id,
-					});
+	/// Schedules the fluid of a chunk that was just loaded, since scheduled ticks aren't saved,
+	/// along with the fluid on the borders of the chunks next to it, which can flow into it now.
+	fn schedule_loaded_fluids(&mut self, chunk_x: i32, chunk_z: i32) {
+		let mut positions = Vec::new();
+
+		for &(dx, dz) in [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+			let chunk = match self.get_chunk(chunk_x + dx, chunk_z + dz) {
+				Some(chunk) => chunk,
+				None => continue,
+			};
+			// of the chunks next to it, only the column of blocks facing it
+			let (min_x, max_x) = match dx {
+				1 => (0, 0),
+				-1 => (15, 15),
+				_ => (0, 15),
+			};
+			let (min_z, max_z) = match dz {
+				1 => (0, 0),
+				-1 => (15, 15),
+				_ => (0, 15),
+			};
+
+			for index in 0..SECTION_COUNT {
+				let section = match chunk.get_section(index) {
+					Some(section) if section.may_contain(BlockType::Water as u16) || section.may_contain(BlockType::Lava as u16) => section,
+					_ => continue,
+				};
+
+				for y in 0..SECTION_SIZE {
+					for z in min_z..max_z + 1 {
+						for x in min_x..max_x + 1 {
+							if Fluid::from_block(section.get(x, y, z).id).is_some() {
+								positions.push((
+									(chunk_x + dx) * 16 + x as i32,
+									(index * SECTION_SIZE + y) as i32,
+									(chunk_z + dz) * 16 + z as i32,
+								));
+							}
+						}
+					}
 				}
 			}
 		}
 
-		vec
+		for (x, y, z) in positions {
+			if fluid::needs_update(self, x, y, z) {
+				let fluid = Fluid::from_block(self.get_block_id(x, y, z)).unwrap();
+				self.schedule_tick(x, y, z, fluid.get_tick_delay());
+			}
+		}
 	}
 
-	pub fn get_instance_buffer(
-		&mut self,
-		display: &mut glium::Display,
-	) -> glium::VertexBuffer<Instance> {
-		glium::VertexBuffer::new(display, &self.get_instance_vector()).unwrap()
+	pub fn remove_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> Option<Chunk> {
+		let chunk = self.chunks.remove(&(chunk_x, chunk_z));
+		self.mark_neighbors_dirty(chunk_x, chunk_z);
+
+		chunk.map(|chunk| Arc::try_unwrap(chunk).unwrap_or_else(|chunk| (*chunk).clone()))
 	}
 
-	fn set_block_ignore_neighbors(&mut self, raw_x: u32, raw_y: u8, raw_z: u32, block: u8) {
-		let x = (raw_x & 15) as u8;
-		let y = raw_y as u8;
-		let z = (raw_z & 15) as u8;
+	/// Returns 0 (air) for positions in unloaded chunks or outside of the world's height.
+	pub fn get_block_id(&self, x: i32, y: i32, z: i32) -> u16 {
+		if y < 0 || y > 255 {
+			return 0;
+		}
 
-		let ux = x as usize;
-		let uy = y as usize;
-		let uz = z as usize;
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		match self.get_chunk(chunk_x, chunk_z) {
+			Some(chunk) => chunk.get_block((x & 15) as usize, y as usize, (z & 15) as usize),
+			None => 0,
+		}
+	}
 
-		self.chunks[(raw_x >> 4) as usize][(raw_z >> 4) as usize].blocks[ux][uz][uy] = block;
+	/// The state of a block, or 0 if it isn't loaded.
+	pub fn get_block_state(&self, x: i32, y: i32, z: i32) -> u16 {
+		self.get_block_data(x, y, z).state
+	}
 
-		if block != 0 {
-			for block_pos in &mut self.get_facial_neighbors(raw_x as i64, y as i16, raw_z as i64) {
-				if block_pos.block_id == 0 {
-					self.chunks[(raw_x >> 4) as usize][(raw_z >> 4) as usize]
-						.visible_blocks
-						.insert(BlockPos::new(raw_x, y, raw_z, block));
-					return;
-				}
+	/// The id and state of a block. Returns air for positions in unloaded chunks or outside of the world's height.
+	pub fn get_block_data(&self, x: i32, y: i32, z: i32) -> BlockData {
+		if y < 0 || y > 255 {
+			return BlockData::air();
+		}
+
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		match self.get_chunk(chunk_x, chunk_z) {
+			Some(chunk) => chunk.get_block_data((x & 15) as usize, y as usize, (z & 15) as usize),
+			None => BlockData::air(),
+		}
+	}
+
+	/// The light level of a block. Unloaded blocks and those above the world are lit by the sky
+	/// only, those below the world are dark.
+	pub fn get_light(&self, light_type: LightType, x: i32, y: i32, z: i32) -> u8 {
+		if y < 0 {
+			return 0;
+		}
+
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		match self.get_chunk(chunk_x, chunk_z) {
+			Some(chunk) if y <= 255 => {
+				chunk.light.get(light_type, (x & 15) as usize, y as usize, (z & 15) as usize)
 			}
+			_ if light_type == LightType::Sky => MAX_LIGHT,
+			_ => 0,
 		}
+	}
+
+	/// Sets the light level of a loaded block and marks the chunks whose faces it lights as dirty.
+	/// Used by the lighting engine, see `lighting::update_light`.
+	pub fn set_light(&mut self, light_type: LightType, x: i32, y: i32, z: i32, level: u8) {
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		match self.get_chunk_mut(chunk_x, chunk_z) {
+			Some(chunk) => chunk.light.set(light_type, (x & 15) as usize, y as usize, (z & 15) as usize, level),
+			None => return,
+		}
+
+		self.mark_dirty(x, z);
+	}
+
+	/// How blocks emit and let through light, which chunks are lit with before they are added.
+	pub fn get_light_properties(&self) -> Arc<LightProperties> {
+		self.light_properties.clone()
+	}
+
+	/// Whether a block hides the faces behind it and blocks light, i.e. isn't transparent.
+	pub fn is_opaque(&self, block: u16) -> bool {
+		self.light_properties.is_opaque(block)
+	}
+
+	pub fn get_block<'a>(&self, blocks: &'a Blocks, x: i32, y: i32, z: i32) -> Option<&'a Block> {
+		blocks.get_block(self.get_block_id(x, y, z))
+	}
+
+	/// The biome of a column, or `None` if it is not loaded.
+	pub fn get_biome(&self, x: i32, z: i32) -> Option<Biome> {
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		self.get_chunk(chunk_x, chunk_z)
+			.map(|chunk| chunk.get_biome((x & 15) as usize, (z & 15) as usize))
+	}
+
+	pub fn is_solid_block(&self, x: f32, y: f32, z: f32) -> bool {
+		let bx = f32::floor(x + 0.5) as i32;
+		let by = f32::floor(y + 0.5) as i32;
+		let bz = f32::floor(z + 0.5) as i32;
+
+		let block = self.get_block_id(bx, by, bz);
+		self.is_loaded(bx, by, bz) && self.solid_blocks.get(block as usize).cloned().unwrap_or(false)
+	}
+
+	/// Sets a block. Does nothing if its chunk isn't loaded.
+	pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: &Block) {
+		self.set_block_data(x, y, z, BlockData::new(block.id, 0));
+	}
 
-		self.chunks[(raw_x >> 4) as usize][(raw_z >> 4) as usize]
-			.visible_blocks
-			.remove(&BlockPos::new(raw_x, y, raw_z, block));
+	/// Sets a block along with its state. Does nothing if its chunk isn't loaded.
+	pub fn set_block_with_state(&mut self, x: i32, y: i32, z: i32, block: &Block, state: u16) {
+		self.set_block_data(x, y, z, BlockData::new(block.id, state));
 	}
 
-	pub fn is_in_world_bounds(&self, x: i64, y: i16, z: i64) -> bool {
-		x >= 0 && z >= 0 && y >= 0 && y <= 255
+	/// Changes the state of the block that is already there. Does nothing for air or unloaded blocks.
+	pub fn set_block_state(&mut self, x: i32, y: i32, z: i32, state: u16) {
+		let block = self.get_block_data(x, y, z);
+		if !block.is_air() && block.state != state {
+			self.set_block_data(x, y, z, BlockData::new(block.id, state));
+		}
+	}
+
+	/// Sets a block placed by a world generation feature such as a tree or structure. Unlike
+	/// `set_block`, this only replaces blocks that rank below the new block in `get_feature_priority`.
+	/// That makes the outcome the same no matter in what order overlapping features are placed,
+	/// which in turn doesn't depend on the order that chunks are generated in.
+	///
+	/// The block isn't lit until `finish_features` is called, so that a whole tree is lit at once.
+	pub fn set_feature_block(&mut self, x: i32, y: i32, z: i32, block: u16) {
+		if self.is_loaded(x, y, z) && can_feature_replace(self.get_block_id(x, y, z), block) {
+			self.set_block_ignore_neighbors(x, y, z, BlockData::new(block, 0));
+			self.feature_blocks.push((x, y, z));
+		}
+	}
+
+	/// Lights the blocks placed with `set_feature_block` since the last call in one go, and marks
+	/// their chunks dirty.
+	pub fn finish_features(&mut self) {
+		let mut positions: Vec<(i32, i32, i32)> = self.feature_blocks.drain(..).collect();
+		positions.sort();
+		positions.dedup();
+
+		for &(x, _, z) in &positions {
+			self.mark_dirty(x, z);
+		}
+
+		let light_properties = self.light_properties.clone();
+		lighting::update_lights(self, &light_properties, &positions);
+
+		for &(x, y, z) in &positions {
+			self.schedule_fluid_updates(x, y, z);
+		}
 	}
 
-	pub fn is_in_rendered_world_bounds(&self, x: i64, y: i16, z: i64) -> bool {
-		x >= 0 && z >= 0 && y >= 0 && y <= 255 && x < self.render_distance as i64 * 16
-			&& z < self.render_distance as i64 * 16
+	/// Sets a block by its id and state. Does nothing if its chunk isn't loaded.
+	pub fn set_block_data(&mut self, x: i32, y: i32, z: i32, block: BlockData) {
+		if !self.is_loaded(x, y, z) {
+			return;
+		}
+
+		self.set_block_ignore_neighbors(x, y, z, block);
+		self.mark_dirty(x, z);
+
+		let light_properties = self.light_properties.clone();
+		lighting::update_light(self, &light_properties, x, y, z);
+		self.schedule_fluid_updates(x, y, z);
 	}
 
-	pub fn is_visible(&self, x: u32, y: u8, z: u32) -> bool {
-		self.chunks[(x >> 4) as usize][(z >> 4) as usize]
-			.visible_blocks
-			.contains(&BlockPos::new(x, y, z, 0))
+	/// Schedules an update of the fluid at and around a block that changed, so that it can flow
+	/// into the space or dry up.
+	fn schedule_fluid_updates(&mut self, x: i32, y: i32, z: i32) {
+		let positions = [
+			(x, y, z),
+			(x, y + 1, z),
+			(x, y - 1, z),
+			(x + 1, y, z),
+			(x - 1, y, z),
+			(x, y, z + 1),
+			(x, y, z - 1),
+		];
+
+		for &(x, y, z) in positions.iter() {
+			if let Some(fluid) = Fluid::from_block(self.get_block_id(x, y, z)) {
+				self.schedule_tick(x, y, z, fluid.get_tick_delay());
+			}
+		}
 	}
 
-	fn add_if_in_bounds(&self, vec: &mut Vec<BlockPos>, x: i64, y: i16, z: i64) {
-		if self.is_in_rendered_world_bounds(x, y, z) {
-			vec.push(BlockPos::new(
-				x as u32,
-				y as u8,
-				z as u32,
-				self.get_block_id(x as u32, y as u8, z as u32),
-			));
+	/// Marks the chunk of a block as needing to be meshed again, along with the chunks next to it
+	/// if the block is on their border. The corners of faces are shaded by the blocks around
+	/// them, so a block in the corner of a chunk also marks the chunk diagonally next to it.
+	fn mark_dirty(&mut self, x: i32, z: i32) {
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		let dx = match x & 15 {
+			0 => -1,
+			15 => 1,
+			_ => 0,
+		};
+		let dz = match z & 15 {
+			0 => -1,
+			15 => 1,
+			_ => 0,
+		};
+
+		self.mark_chunk_dirty(chunk_x, chunk_z);
+		if dx != 0 {
+			self.mark_chunk_dirty(chunk_x + dx, chunk_z);
+		}
+		if dz != 0 {
+			self.mark_chunk_dirty(chunk_x, chunk_z + dz);
+		}
+		if dx != 0 && dz != 0 {
+			self.mark_chunk_dirty(chunk_x + dx, chunk_z + dz);
+		}
+	}
+
+	pub fn mark_chunk_dirty(&mut self, chunk_x: i32, chunk_z: i32) {
+		// checked first, so that a chunk held by a mesh job isn't copied just to be marked again
+		if self.get_chunk(chunk_x, chunk_z).map_or(false, |chunk| !chunk.dirty) {
+			self.get_chunk_mut(chunk_x, chunk_z).unwrap().dirty = true;
+		}
+	}
+
+	/// Marks the eight chunks around a chunk as dirty, since the faces along their borders are
+	/// both culled and shaded by its blocks.
+	fn mark_neighbors_dirty(&mut self, chunk_x: i32, chunk_z: i32) {
+		for &(dx, dz) in NEIGHBOR_CHUNK_OFFSETS.iter() {
+			self.mark_chunk_dirty(chunk_x + dx, chunk_z + dz);
+		}
+	}
+
+	fn set_block_ignore_neighbors(&mut self, x: i32, y: i32, z: i32, block: BlockData) {
+		let ux = (x & 15) as usize;
+		let uy = y as usize;
+		let uz = (z & 15) as usize;
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+
+		if let Some(chunk) = self.get_chunk_mut(chunk_x, chunk_z) {
+			chunk.set_block_data(ux, uy, uz, block);
+		}
+	}
+
+	/// Whether the block at the given position lies in a loaded chunk and inside of the world's height.
+	pub fn is_loaded(&self, x: i32, y: i32, z: i32) -> bool {
+		let (chunk_x, chunk_z) = get_chunk_position(x, z);
+		y >= 0 && y <= 255 && self.is_chunk_loaded(chunk_x, chunk_z)
+	}
+
+	/// Whether a block isn't air and touches air or an unloaded block, so that it can be seen.
+	pub fn is_visible(&self, x: i32, y: i32, z: i32) -> bool {
+		self.get_block_id(x, y, z) != 0
+			&& self
+				.get_facial_neighbors(x, y, z)
+				.iter()
+				.any(|block_pos| block_pos.block_id == 0)
+	}
+
+	fn add_if_loaded(&self, vec: &mut Vec<BlockPos>, x: i32, y: i32, z: i32) {
+		if self.is_loaded(x, y, z) {
+			vec.push(BlockPos::new(x, y as u8, z, self.get_block_id(x, y, z)));
 		} else {
 			vec.push(BlockPos::new(0, 0, 0, 0));
 		}
 	}
 
-	pub fn get_facial_neighbors(&mut self, x: i64, y: i16, z: i64) -> Vec<BlockPos> {
+	pub fn get_facial_neighbors(&self, x: i32, y: i32, z: i32) -> Vec<BlockPos> {
 		let mut neighbors = Vec::new();
 
-		self.add_if_in_bounds(&mut neighbors, x, y + 1, z);
-		self.add_if_in_bounds(&mut neighbors, x, y - 1, z);
-		self.add_if_in_bounds(&mut neighbors, x + 1, y, z);
-		self.add_if_in_bounds(&mut neighbors, x - 1, y, z);
-		self.add_if_in_bounds(&mut neighbors, x, y, z + 1);
-		self.add_if_in_bounds(&mut neighbors, x, y, z - 1);
+		self.add_if_loaded(&mut neighbors, x, y + 1, z);
+		self.add_if_loaded(&mut neighbors, x, y - 1, z);
+		self.add_if_loaded(&mut neighbors, x + 1, y, z);
+		self.add_if_loaded(&mut neighbors, x - 1, y, z);
+		self.add_if_loaded(&mut neighbors, x, y, z + 1);
+		self.add_if_loaded(&mut neighbors, x, y, z - 1);
 
 		neighbors
 	}
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BlockPos {
-	pub x: u32,
+	pub x: i32,
 	pub y: u8,
-	pub z: u32,
-	pub block_id: u8,
+	pub z: i32,
+	pub block_id: u16,
 }
 
 impl BlockPos {
-	pub fn new(x: u32, y: u8, z: u32, block_id: u8) -> BlockPos {
+	pub fn new(x: i32, y: u8, z: i32, block_id: u16) -> BlockPos {
 		BlockPos {
 			x: x,
 			y: y,
@@ -175,7 +487,7 @@ impl PartialEq for BlockPos {
 
 #[derive(Copy, Clone)]
 pub struct ItemStack {
-	pub id: u8,
+	pub id: u16,
 	pub count: u8,
 	pub max: u8,
 }
@@ -183,13 +495,13 @@ pub struct ItemStack {
 impl ItemStack {
 	pub fn new_block(id: BlockType, count: u8) -> ItemStack {
 		ItemStack {
-			id: id as u8,
+			id: id as u16,
 			count: count,
 			max: 64,
 		}
 	}
 
-	pub fn new(id: u8, count: u8, max: u8) -> ItemStack {
+	pub fn new(id: u16, count: u8, max: u8) -> ItemStack {
 		ItemStack {
 			id: id,
 			count: count,
@@ -295,37 +607,134 @@ impl Hash for BlockPos {
 
 impl Eq for BlockPos {}
 
-use std::collections::HashSet;
+#[derive(Clone)]
 pub struct Chunk {
-	pub blocks: Vec<Vec<Vec<u8>>>,
-	pub visible_blocks: HashSet<BlockPos>,
+	/// The chunk's 16-high sections from the bottom up. Sections that are all air are `None`.
+	sections: Vec<Option<ChunkSection>>,
+	/// The biome of every column, indexed by `x * 16 + z`.
+	pub biomes: Vec<u8>,
+	/// Worked out when the chunk is loaded, see `lighting::light_chunk`, and kept up to date once it
+	/// is in a world.
+	pub light: ChunkLight,
+	/// Whether the chunk's blocks changed since it was last meshed.
+	pub dirty: bool,
+	/// Whether the chunk's trees and structures were placed, which waits until the chunks around
+	/// it are loaded, see `worldgen::Decorator`.
+	pub decorated: bool,
 }
 
-use glium;
+impl Chunk {
+	pub fn get_block(&self, x: usize, y: usize, z: usize) -> u16 {
+		self.get_block_data(x, y, z).id
+	}
 
-pub struct Block {
-	pub id: u8,
-	pub drop_id: u8,
-}
+	pub fn get_block_data(&self, x: usize, y: usize, z: usize) -> BlockData {
+		match self.sections[y / SECTION_SIZE] {
+			Some(ref section) => section.get(x, y % SECTION_SIZE, z),
+			None => BlockData::air(),
+		}
+	}
 
-use utils;
+	/// Sets a block with its state reset to 0.
+	pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u16) {
+		self.set_block_data(x, y, z, BlockData::new(block, 0));
+	}
 
-impl Block {
-	pub fn new(id: BlockType) -> Block {
-		let block_id: u8 = id as u8;
-		Block {
-			id: block_id,
-			drop_id: block_id,
+	pub fn set_block_data(&mut self, x: usize, y: usize, z: usize, block: BlockData) {
+		let section = &mut self.sections[y / SECTION_SIZE];
+
+		if section.is_none() {
+			if block.is_air() {
+				return;
+			}
+
+			*section = Some(ChunkSection::new(BlockData::air()));
+		}
+
+		let empty = {
+			let section = section.as_mut().unwrap();
+			section.set(x, y % SECTION_SIZE, z, block);
+			section.is_empty()
+		};
+
+		if empty {
+			*section = None;
 		}
 	}
 
-	pub fn new_with_drop_type(id: BlockType, drop_id: BlockType) -> Block {
-		Block {
-			id: id as u8,
-			drop_id: drop_id as u8,
+	/// The section at the given index from the bottom, or `None` if it is all air.
+	pub fn get_section(&self, index: usize) -> Option<&ChunkSection> {
+		self.sections[index].as_ref()
+	}
+
+	pub fn get_biome(&self, x: usize, z: usize) -> Biome {
+		Biome::from_id(self.biomes[x * 16 + z])
+	}
+
+	pub fn set_biome(&mut self, x: usize, z: usize, biome: Biome) {
+		self.biomes[x * 16 + z] = biome as u8;
+	}
+
+	/// Writes the chunk's blocks and biomes to a save file buffer.
+	pub fn to_buffer(&self, buf: &mut ByteBuffer) {
+		buf.write_u8(self.decorated as u8);
+		buf.write_bytes(&self.biomes);
+
+		for section in &self.sections {
+			match *section {
+				Some(ref section) => {
+					buf.write_u8(1);
+					section.to_buffer(buf);
+				}
+				None => buf.write_u8(0),
+			}
+		}
+	}
+
+	/// Reads a chunk written by `to_buffer`. Truncated or corrupt data is an error rather than a panic,
+	/// so the caller can generate the chunk again instead. So are blocks for which `is_defined` is
+	/// false, e.g. ones that were removed from the block definitions since the chunk was saved.
+	pub fn from_buffer(buf: &mut ByteBuffer, is_defined: &Fn(u16) -> bool) -> io::Result<Chunk> {
+		let mut chunk = create_chunk();
+		check_remaining(buf, 1 + 256)?;
+		chunk.decorated = buf.read_u8() == 1;
+		chunk.biomes = buf.read_bytes(256);
+		if chunk.biomes.iter().any(|&biome| biome as usize >= BIOMES.len()) {
+			return Err(invalid_data("chunk has an unknown biome".to_string()));
+		}
+
+		for index in 0..SECTION_COUNT {
+			check_remaining(buf, 1)?;
+			if buf.read_u8() == 1 {
+				chunk.sections[index] = Some(ChunkSection::from_buffer(buf, is_defined)?);
+			}
 		}
+
+		Ok(chunk)
 	}
+}
+
+use glium;
 
+pub struct Block {
+	pub id: u16,
+	pub name: String,
+	// nothing breaks blocks yet, the registry already validates this and `hardness`
+	#[allow(dead_code)]
+	pub drop_id: u16,
+	pub solid: bool,
+	pub transparent: bool,
+	pub layer: RenderLayer,
+	/// How long the block takes to break.
+	#[allow(dead_code)]
+	pub hardness: f32,
+	/// The light level the block emits, from 0 to 15.
+	pub light: u8,
+	/// The texture of every face in the order back, top, front, bottom, right, left. Empty for air.
+	pub textures: Vec<String>,
+}
+
+impl Block {
 	pub fn get_vertex_buffer(display: &mut glium::Display) -> glium::VertexBuffer<Vertex> {
 		let mut vertices = vec![
 			Vertex {
@@ -471,81 +880,145 @@ impl Block {
 	}
 }
 
+/// Which pass a block is drawn in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderLayer {
+	/// Drawn first, without blending.
+	Opaque = 0,
+	/// Like opaque, except that pixels with little alpha are left out, e.g. the holes in leaves.
+	Cutout = 1,
+	/// Blended with what's behind it, so it's drawn last and sorted from back to front.
+	Translucent = 2,
+}
+
+pub const RENDER_LAYERS: [RenderLayer; 3] = [RenderLayer::Opaque, RenderLayer::Cutout, RenderLayer::Translucent];
+
+/// The blocks the game itself refers to, e.g. in world generation. Every other block only exists in
+/// the block definition file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BlockType {
 	Air = 0,
 	Stone = 1,
 	Cobblestone = 2,
 	Dirt = 3,
 	Grass = 4,
+	Sand = 5,
+	CoalOre = 6,
+	IronOre = 7,
+	GoldOre = 8,
+	DiamondOre = 9,
+	Log = 10,
+	Leaves = 11,
+	Water = 14,
+	Lava = 15,
+}
+
+pub const BLOCK_TYPES: [BlockType; 14] = [
+	BlockType::Air,
+	BlockType::Stone,
+	BlockType::Cobblestone,
+	BlockType::Dirt,
+	BlockType::Grass,
+	BlockType::Sand,
+	BlockType::CoalOre,
+	BlockType::IronOre,
+	BlockType::GoldOre,
+	BlockType::DiamondOre,
+	BlockType::Log,
+	BlockType::Leaves,
+	BlockType::Water,
+	BlockType::Lava,
+];
+
+impl BlockType {
+	/// The name of the block in the block definition file.
+	pub fn get_name(&self) -> &'static str {
+		match *self {
+			BlockType::Air => "air",
+			BlockType::Stone => "stone",
+			BlockType::Cobblestone => "cobblestone",
+			BlockType::Dirt => "dirt",
+			BlockType::Grass => "grass",
+			BlockType::Sand => "sand",
+			BlockType::CoalOre => "coal_ore",
+			BlockType::IronOre => "iron_ore",
+			BlockType::GoldOre => "gold_ore",
+			BlockType::DiamondOre => "diamond_ore",
+			BlockType::Log => "log",
+			BlockType::Leaves => "leaves",
+			BlockType::Water => "water",
+			BlockType::Lava => "lava",
+		}
+	}
 }
 
+pub const BLOCKS_FILE: &str = "data/blocks.json";
+
 pub struct Blocks {
-	pub block_map: Vec<Block>,
+	/// The blocks by id, `None` for ids that no block has.
+	pub block_map: Vec<Option<Block>>,
+	names: HashMap<String, u16>,
 }
 
 impl Blocks {
-	pub fn new() -> Blocks {
-		let mut blocks = Blocks {
-			block_map: Vec::new(),
-		};
-
-		blocks.block_map.push(Block::new(BlockType::Air));
-		blocks.block_map.push(Block::new_with_drop_type(
-			BlockType::Stone,
-			BlockType::Cobblestone,
-		));
-		blocks.block_map.push(Block::new(BlockType::Cobblestone));
-		blocks.block_map.push(Block::new(BlockType::Dirt));
-		blocks.block_map.push(Block::new(BlockType::Grass));
+	/// Loads the block definitions from a JSON file, see `data/blocks.json`.
+	pub fn load(path: &str) -> Result<Blocks, BlockRegistryError> {
+		Blocks::from_json(&fs::read_to_string(path)?)
+	}
 
-		blocks
+	pub fn from_json(json: &str) -> Result<Blocks, BlockRegistryError> {
+		let block_map = parse_blocks(json)?;
+		let names = block_map
+			.iter()
+			.filter_map(|block| block.as_ref())
+			.map(|block| (block.name.clone(), block.id))
+			.collect();
+
+		Ok(Blocks {
+			block_map: block_map,
+			names: names,
+		})
 	}
 
-	pub fn get_block(&self, id: u8) -> &Block {
-		self.block_map.get(id as usize).unwrap()
+	/// The block with the given id, or `None` if no block has it.
+	pub fn get_block(&self, id: u16) -> Option<&Block> {
+		self.block_map.get(id as usize).and_then(|block| block.as_ref())
 	}
 
+	/// The blocks of `BlockType` are always defined, `parse_blocks` makes sure of it.
 	pub fn block(&self, id: BlockType) -> &Block {
-		self.block_map.get(id as usize).unwrap()
+		self.get_block(id as u16).unwrap()
 	}
 
-	pub fn get_block_count(&self) -> f32 {
-		self.block_map.len() as f32 - 1.0
+	pub fn get_block_by_name(&self, name: &str) -> Option<&Block> {
+		self.names.get(name).and_then(|id| self.get_block(*id))
 	}
 }
 
-pub fn create_world(air_block: u8, render_distance: usize) -> World {
-	let mut chunk_array = Vec::with_capacity(render_distance);
-
-	for x in 0..render_distance {
-		chunk_array.push(Vec::with_capacity(render_distance));
-		for _ in 0..render_distance {
-			chunk_array[x].push(create_chunk(air_block));
-		}
-	}
-
+pub fn create_world(render_distance: u8, blocks: &Blocks) -> World {
 	World {
-		chunks: chunk_array,
-		render_distance: render_distance as u8,
+		chunks: HashMap::new(),
+		render_distance: render_distance,
+		feature_blocks: Vec::new(),
+		light_properties: Arc::new(LightProperties::new(blocks)),
+		solid_blocks: blocks
+			.block_map
+			.iter()
+			.map(|block| block.as_ref().map_or(false, |block| block.solid))
+			.collect(),
+		time: WorldTime::new(0),
+		ticks: TickScheduler::new(0),
 	}
 }
 
-fn create_chunk(air_block: u8) -> Chunk {
-	let mut block_array = Vec::with_capacity(16);
-
-	for x in 0..16 {
-		block_array.push(Vec::with_capacity(16));
-		for z in 0..16 {
-			block_array[x].push(Vec::with_capacity(255));
-			for _ in 0..256 {
-				block_array[x][z].push(air_block);
-			}
-		}
-	}
-
+/// Creates a chunk that is all air.
+pub fn create_chunk() -> Chunk {
 	Chunk {
-		blocks: block_array,
-		visible_blocks: HashSet::new(),
+		sections: vec![None; SECTION_COUNT],
+		biomes: vec![Biome::Plains as u8; 256],
+		light: ChunkLight::new(),
+		dirty: true,
+		decorated: false,
 	}
 }
 
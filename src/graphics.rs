@@ -4,15 +4,69 @@ use glium::glutin::*;
 use glium::index::*;
 use glium::texture::*;
 use glium::*;
-use std::cell::RefCell;
+use std::cell::{RefCell, RefMut};
 
 use camera::Camera;
 use game::Vertex;
+use image::RgbaImage;
+use mesher::BlockVertex;
 use std::collections::HashMap;
+use time::Sky;
 use utils;
 
+// fog starts this far into the distance it covers, the rest of the way it thickens
+const FOG_START_FRACTION: f32 = 0.6;
+// the chunk the camera is in is always loaded, so the fog never gets closer than its width
+const MIN_FOG_END: f32 = 16.0;
+
+/// Settings for how the world is drawn.
+pub struct GraphicsConfig {
+    /// The color the screen is cleared to before drawing, or `None` for the color of the sky at
+    /// the horizon.
+    pub clear_color: Option<[f32; 3]>,
+    /// The distance in blocks at which fog starts to cover the world.
+    pub fog_start: f32,
+    /// The distance in blocks from which everything is hidden by fog.
+    pub fog_end: f32,
+    /// The color of the fog, or `None` to match the sky at the horizon so that distant chunks
+    /// fade into it.
+    pub fog_color: Option<[f32; 3]>,
+}
+
+impl GraphicsConfig {
+    /// Fog that thickens towards the edge of the chunks loaded around the camera, so that the
+    /// world fades out instead of ending abruptly.
+    pub fn new(load_radius: u8) -> GraphicsConfig {
+        let mut config = GraphicsConfig {
+            clear_color: None,
+            fog_start: 0.0,
+            fog_end: 0.0,
+            fog_color: None,
+        };
+        config.set_fog_distance(load_radius);
+
+        config
+    }
+
+    /// Moves the fog to the edge of the given chunk radius. Chunks are loaded around the chunk
+    /// the camera is in, so only a radius one chunk smaller is loaded in every direction.
+    pub fn set_fog_distance(&mut self, load_radius: u8) {
+        self.fog_end = f32::max(MIN_FOG_END, (load_radius.max(1) - 1) as f32 * 16.0);
+        self.fog_start = self.fog_end * FOG_START_FRACTION;
+    }
+
+    pub fn get_clear_color(&self, sky: &Sky) -> [f32; 3] {
+        self.clear_color.unwrap_or(sky.horizon_color)
+    }
+
+    pub fn get_fog_color(&self, sky: &Sky) -> [f32; 3] {
+        self.fog_color.unwrap_or(sky.horizon_color)
+    }
+}
+
 pub struct Graphics<'a> {
     pub display: Display,
+    pub config: GraphicsConfig,
     pub window_size: (f32, f32),
     pub shaders: HashMap<String, Program>,
     pub images: HashMap<String, Texture2d>,
@@ -22,9 +76,9 @@ pub struct Graphics<'a> {
 }
 
 impl<'a> Graphics<'a> {
-    pub fn new() -> Graphics<'a> {
+    pub fn new(config: GraphicsConfig) -> Graphics<'a> {
         implement_vertex!(Vertex, position, uv, face);
-        implement_vertex!(Instance, matrix, id);
+        implement_vertex!(BlockVertex, position, uv, tile, light, occlusion);
         implement_vertex!(Vertex2D, position, uv);
 
         let events_loop = EventsLoop::new();
@@ -41,6 +95,7 @@ impl<'a> Graphics<'a> {
 
         let mut graphics = Graphics {
             display: display,
+            config: config,
             window_size: (window_size.0 as f32, window_size.1 as f32),
             shaders: HashMap::new(),
             images: HashMap::new(),
@@ -82,12 +137,10 @@ impl<'a> Graphics<'a> {
     }
 
     pub fn load_standard_images(&mut self) {
-        self.load_image("atlas", "textures/blocks/atlas_old.png");
         self.load_image("crosshair", "textures/crosshair.png");
         self.load_image("hotbar", "textures/hotbar.png");
         self.load_image("hotbar_selected", "textures/hotbar_selected.png");
         self.load_image("text", "textures/numbers.png");
-        self.load_image("skybox", "textures/skybox.png");
     }
 
     pub fn load_standard_transforms_2d(&mut self) {
@@ -126,6 +179,20 @@ impl<'a> Graphics<'a> {
                 ..Default::default()
             },
         );
+        self.load_draw_params(
+            "block_translucent",
+            DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    // translucent faces are sorted instead, and shouldn't hide the ones behind them
+                    write: false,
+                    ..Default::default()
+                },
+                blend: Blend::alpha_blending(),
+                backface_culling: BackfaceCullingMode::CullCounterClockwise,
+                ..Default::default()
+            },
+        );
         self.load_draw_params(
             "empty",
             DrawParameters {
@@ -187,6 +254,14 @@ impl<'a> Graphics<'a> {
         self.images.insert(String::from(name), sampler_raw);
     }
 
+    /// Uploads an image that was created in memory, such as the block atlas, along with its mipmaps.
+    pub fn load_image_from_rgba(&mut self, name: &str, image: &RgbaImage) {
+        let raw = RawImage2d::from_raw_rgba_reversed(&image.clone().into_raw()[..], image.dimensions());
+        let sampler_raw =
+            Texture2d::with_mipmaps(&mut self.display, raw, MipmapsOption::AutoGeneratedMipmaps).unwrap();
+        self.images.insert(String::from(name), sampler_raw);
+    }
+
     pub fn get_image(&self, name: &str) -> &Texture2d {
         self.images.get(name).unwrap()
     }
@@ -235,9 +310,10 @@ impl<'a> Graphics<'a> {
             .unwrap();
     }*/
 
-    pub fn start(&mut self) {
+    pub fn start(&mut self, sky: &Sky) {
+        let color = self.config.get_clear_color(sky);
         let mut target = self.display.draw();
-        target.clear_color_and_depth((1.0, 1.0, 1.0, 1.0), 1.0);
+        target.clear_color_and_depth((color[0], color[1], color[2], 1.0), 1.0);
 
         self.target.replace(Some(target));
     }
@@ -246,21 +322,23 @@ impl<'a> Graphics<'a> {
         self.target.replace(None).unwrap().finish().unwrap();
     }
 
-    pub fn get_frame(&self) -> &mut Frame {
-        self.target.borrow_mut().as_mut().unwrap()
+    pub fn get_frame(&self) -> RefMut<Frame> {
+        RefMut::map(self.target.borrow_mut(), |target| target.as_mut().unwrap())
     }
 }
 
 pub struct GraphicsParams {
     pub view_matrix: [[f32; 4]; 4],
     pub projection_matrix: [[f32; 4]; 4],
+    pub sky: Sky,
 }
 
 impl GraphicsParams {
-    pub fn new(view_matrix: [[f32; 4]; 4], projection_matrix: [[f32; 4]; 4]) -> GraphicsParams {
+    pub fn new(view_matrix: [[f32; 4]; 4], projection_matrix: [[f32; 4]; 4], sky: Sky) -> GraphicsParams {
         GraphicsParams {
             view_matrix,
             projection_matrix,
+            sky,
         }
     }
 }
@@ -269,12 +347,6 @@ pub trait GameObject {
     fn draw(&self, graphics: &mut Graphics, params: &GraphicsParams);
 }
 
-#[derive(Clone, Copy)]
-pub struct Instance {
-    pub matrix: [[f32; 4]; 4],
-    pub id: u8,
-}
-
 #[derive(Copy, Clone)]
 pub struct Vertex2D {
     pub position: [f32; 2],
@@ -289,3 +361,19 @@ impl Vertex2D {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fog_distance() {
+        for load_radius in 0..4 {
+            let config = GraphicsConfig::new(load_radius);
+            assert!(config.fog_end >= MIN_FOG_END);
+            assert!(config.fog_start < config.fog_end);
+        }
+
+        assert_eq!(GraphicsConfig::new(8).fog_end, 7.0 * 16.0);
+    }
+}
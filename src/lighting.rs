@@ -0,0 +1,381 @@
+use chunk::{SECTION_COUNT, SECTION_SIZE};
+use game::{Blocks, Chunk, World};
+use std::collections::VecDeque;
+
+/// The brightest light level, that of direct sky light and the brightest light sources.
+pub const MAX_LIGHT: u8 = 15;
+
+const SECTION_VOLUME: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+const WORLD_HEIGHT: i32 = (SECTION_COUNT * SECTION_SIZE) as i32;
+
+// full sky light and no block light, packed like the light of a block in `ChunkLight`
+const DEFAULT_LIGHT: u8 = MAX_LIGHT << 4;
+
+const NEIGHBORS: [(i32, i32, i32); 6] = [(0, -1, 0), (0, 1, 0), (1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightType {
+    /// Light coming down from the sky. It goes straight down without getting dimmer.
+    Sky,
+    /// Light given off by blocks such as glowstone.
+    Block,
+}
+
+pub const LIGHT_TYPES: [LightType; 2] = [LightType::Sky, LightType::Block];
+
+/// What lighting needs to know about every block: how much light it gives off and whether it
+/// lets light through.
+pub struct LightProperties {
+    emission: Vec<u8>,
+    opaque: Vec<bool>,
+    defined: Vec<bool>,
+}
+
+impl LightProperties {
+    pub fn new(blocks: &Blocks) -> LightProperties {
+        LightProperties {
+            emission: blocks
+                .block_map
+                .iter()
+                .map(|block| block.as_ref().map_or(0, |block| block.light))
+                .collect(),
+            opaque: blocks
+                .block_map
+                .iter()
+                .map(|block| block.as_ref().map_or(false, |block| !block.transparent))
+                .collect(),
+            defined: blocks.block_map.iter().map(|block| block.is_some()).collect(),
+        }
+    }
+
+    /// Whether the block has a definition. Chunks can only contain blocks that do.
+    pub fn is_defined(&self, block: u16) -> bool {
+        self.defined.get(block as usize).cloned().unwrap_or(false)
+    }
+
+    pub fn get_emission(&self, block: u16) -> u8 {
+        self.emission[block as usize]
+    }
+
+    pub fn is_opaque(&self, block: u16) -> bool {
+        self.opaque[block as usize]
+    }
+}
+
+/// The sky and block light of every block in a chunk, 4 bits each. Light isn't saved, it is
+/// worked out again whenever a chunk is added to the world.
+#[derive(Clone)]
+pub struct ChunkLight {
+    /// The light of each section from the bottom up. Sections that are `None` are fully lit by the
+    /// sky and have no block light, like the empty sections above the terrain.
+    sections: Vec<Option<Vec<u8>>>,
+}
+
+impl ChunkLight {
+    pub fn new() -> ChunkLight {
+        ChunkLight {
+            sections: vec![None; SECTION_COUNT],
+        }
+    }
+
+    pub fn get(&self, light_type: LightType, x: usize, y: usize, z: usize) -> u8 {
+        let packed = match self.sections[y / SECTION_SIZE] {
+            Some(ref section) => section[get_light_index(x, y % SECTION_SIZE, z)],
+            None => DEFAULT_LIGHT,
+        };
+
+        match light_type {
+            LightType::Sky => packed >> 4,
+            LightType::Block => packed & 15,
+        }
+    }
+
+    pub fn set(&mut self, light_type: LightType, x: usize, y: usize, z: usize, level: u8) {
+        if self.get(light_type, x, y, z) == level {
+            return;
+        }
+
+        let section = self.sections[y / SECTION_SIZE].get_or_insert_with(|| vec![DEFAULT_LIGHT; SECTION_VOLUME]);
+        let packed = &mut section[get_light_index(x, y % SECTION_SIZE, z)];
+
+        *packed = match light_type {
+            LightType::Sky => (*packed & 15) | (level << 4),
+            LightType::Block => (*packed & !15) | level,
+        };
+    }
+}
+
+fn get_light_index(x: usize, y: usize, z: usize) -> usize {
+    (y * SECTION_SIZE + z) * SECTION_SIZE + x
+}
+
+/// Something light can spread through, either a single chunk or the whole world.
+pub trait LightVolume {
+    /// The block at a position, or `None` if it is outside of the volume. Light doesn't spread
+    /// into or out of positions outside of the volume.
+    fn get_loaded_block(&self, x: i32, y: i32, z: i32) -> Option<u16>;
+
+    fn get_light_level(&self, light_type: LightType, x: i32, y: i32, z: i32) -> u8;
+
+    fn set_light_level(&mut self, light_type: LightType, x: i32, y: i32, z: i32, level: u8);
+}
+
+/// A chunk on its own, in local coordinates.
+impl LightVolume for Chunk {
+    fn get_loaded_block(&self, x: i32, y: i32, z: i32) -> Option<u16> {
+        if x < 0 || x > 15 || z < 0 || z > 15 || y < 0 || y >= WORLD_HEIGHT {
+            return None;
+        }
+
+        Some(self.get_block(x as usize, y as usize, z as usize))
+    }
+
+    fn get_light_level(&self, light_type: LightType, x: i32, y: i32, z: i32) -> u8 {
+        self.light.get(light_type, x as usize, y as usize, z as usize)
+    }
+
+    fn set_light_level(&mut self, light_type: LightType, x: i32, y: i32, z: i32, level: u8) {
+        self.light.set(light_type, x as usize, y as usize, z as usize, level);
+    }
+}
+
+/// The loaded chunks of a world, in world coordinates.
+impl LightVolume for World {
+    fn get_loaded_block(&self, x: i32, y: i32, z: i32) -> Option<u16> {
+        if self.is_loaded(x, y, z) {
+            Some(self.get_block_id(x, y, z))
+        } else {
+            None
+        }
+    }
+
+    fn get_light_level(&self, light_type: LightType, x: i32, y: i32, z: i32) -> u8 {
+        self.get_light(light_type, x, y, z)
+    }
+
+    fn set_light_level(&mut self, light_type: LightType, x: i32, y: i32, z: i32, level: u8) {
+        self.set_light(light_type, x, y, z, level);
+    }
+}
+
+/// Works out the light of a chunk as if there was nothing around it. Sky light shines down every
+/// column until it hits an opaque block, then both kinds of light spread out inside of the chunk.
+pub fn light_chunk(chunk: &mut Chunk, properties: &LightProperties) {
+    chunk.light = ChunkLight::new();
+
+    let top = (0..SECTION_COUNT)
+        .rev()
+        .find(|index| chunk.get_section(*index).is_some())
+        .map_or(0, |index| ((index + 1) * SECTION_SIZE) as i32);
+    let mut sky_queue = VecDeque::new();
+    let mut block_queue = VecDeque::new();
+
+    for x in 0..16 {
+        for z in 0..16 {
+            let mut sky_light = MAX_LIGHT;
+
+            for y in (0..top).rev() {
+                let block = chunk.get_block(x as usize, y as usize, z as usize);
+                if properties.is_opaque(block) {
+                    sky_light = 0;
+                }
+
+                if sky_light == 0 {
+                    chunk.set_light_level(LightType::Sky, x, y, z, 0);
+                } else {
+                    // spreads sideways into the shadows of the columns around it
+                    sky_queue.push_back((x, y, z));
+                }
+
+                let emission = properties.get_emission(block);
+                if emission > 0 {
+                    chunk.set_light_level(LightType::Block, x, y, z, emission);
+                    block_queue.push_back((x, y, z));
+                }
+            }
+        }
+    }
+
+    spread_light(chunk, properties, LightType::Sky, sky_queue);
+    spread_light(chunk, properties, LightType::Block, block_queue);
+}
+
+/// Lets light flow both ways between a newly added chunk and the loaded chunks next to it.
+pub fn light_chunk_borders(world: &mut World, properties: &LightProperties, chunk_x: i32, chunk_z: i32) {
+    let (x, z) = (chunk_x * 16, chunk_z * 16);
+    // each neighbouring chunk with the first block of the border facing it, the direction
+    // towards the neighbour and the direction along the border
+    let borders = [
+        ((chunk_x - 1, chunk_z), (x, z), (-1, 0), (0, 1)),
+        ((chunk_x + 1, chunk_z), (x + 15, z), (1, 0), (0, 1)),
+        ((chunk_x, chunk_z - 1), (x, z), (0, -1), (1, 0)),
+        ((chunk_x, chunk_z + 1), (x, z + 15), (0, 1), (1, 0)),
+    ];
+
+    for &light_type in LIGHT_TYPES.iter() {
+        let mut queue = VecDeque::new();
+
+        for &(neighbor, start, outwards, along) in borders.iter() {
+            if !world.is_chunk_loaded(neighbor.0, neighbor.1) {
+                continue;
+            }
+
+            for i in 0..16 {
+                let inside = (start.0 + along.0 * i, start.1 + along.1 * i);
+                let outside = (inside.0 + outwards.0, inside.1 + outwards.1);
+
+                for y in 0..WORLD_HEIGHT {
+                    let inside_level = world.get_light(light_type, inside.0, y, inside.1);
+                    let outside_level = world.get_light(light_type, outside.0, y, outside.1);
+
+                    if inside_level > outside_level + 1 {
+                        queue.push_back((inside.0, y, inside.1));
+                    } else if outside_level > inside_level + 1 {
+                        queue.push_back((outside.0, y, outside.1));
+                    }
+                }
+            }
+        }
+
+        spread_light(world, properties, light_type, queue);
+    }
+}
+
+/// Updates the light around a block that was just changed, e.g. after a block was placed in the
+/// sunlight or a light source was broken. First the light that may have passed through or come
+/// from the block is taken away, then the light around the darkened area spreads back into it.
+pub fn update_light<V: LightVolume>(volume: &mut V, properties: &LightProperties, x: i32, y: i32, z: i32) {
+    update_lights(volume, properties, &[(x, y, z)]);
+}
+
+/// Like `update_light`, but for many blocks that changed at once, such as the blocks of a tree.
+/// Their light is taken away and spread back in a single pass, instead of one for every block.
+pub fn update_lights<V: LightVolume>(volume: &mut V, properties: &LightProperties, positions: &[(i32, i32, i32)]) {
+    let changed: Vec<((i32, i32, i32), u16)> = positions
+        .iter()
+        .filter_map(|&(x, y, z)| volume.get_loaded_block(x, y, z).map(|block| ((x, y, z), block)))
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    for &light_type in LIGHT_TYPES.iter() {
+        let mut removed = VecDeque::new();
+        for &((x, y, z), _) in &changed {
+            removed.push_back(((x, y, z), volume.get_light_level(light_type, x, y, z)));
+            volume.set_light_level(light_type, x, y, z, 0);
+        }
+
+        let mut queue = remove_light(volume, properties, light_type, removed);
+        for &((x, y, z), block) in &changed {
+            for &(dx, dy, dz) in NEIGHBORS.iter() {
+                if volume.get_loaded_block(x + dx, y + dy, z + dz).is_some() {
+                    queue.push_back((x + dx, y + dy, z + dz));
+                }
+            }
+
+            let level = match light_type {
+                LightType::Block => properties.get_emission(block),
+                LightType::Sky => {
+                    let below_sky =
+                        y == WORLD_HEIGHT - 1 || volume.get_light_level(LightType::Sky, x, y + 1, z) == MAX_LIGHT;
+                    if below_sky && !properties.is_opaque(block) {
+                        MAX_LIGHT
+                    } else {
+                        0
+                    }
+                }
+            };
+
+            if level > 0 {
+                volume.set_light_level(light_type, x, y, z, level);
+                queue.push_back((x, y, z));
+            }
+        }
+
+        spread_light(volume, properties, light_type, queue);
+    }
+}
+
+/// Spreads the light of every position in the queue to its neighbours, and from there to theirs,
+/// until it fades out or is stopped by opaque blocks.
+pub fn spread_light<V: LightVolume>(
+    volume: &mut V,
+    properties: &LightProperties,
+    light_type: LightType,
+    mut queue: VecDeque<(i32, i32, i32)>,
+) {
+    while let Some((x, y, z)) = queue.pop_front() {
+        let level = volume.get_light_level(light_type, x, y, z);
+
+        for &(dx, dy, dz) in NEIGHBORS.iter() {
+            let spread_level = get_spread_level(light_type, level, dy);
+            if spread_level == 0 {
+                continue;
+            }
+
+            let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+            match volume.get_loaded_block(nx, ny, nz) {
+                Some(block) if !properties.is_opaque(block) => {
+                    if volume.get_light_level(light_type, nx, ny, nz) < spread_level {
+                        volume.set_light_level(light_type, nx, ny, nz, spread_level);
+                        queue.push_back((nx, ny, nz));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Darkens everything that was lit by the given positions, which have already been set to 0 and
+/// are listed with the light they had before. Returns the lit positions along the edge of the
+/// darkened area, whose light has to be spread back into it.
+fn remove_light<V: LightVolume>(
+    volume: &mut V,
+    properties: &LightProperties,
+    light_type: LightType,
+    mut queue: VecDeque<((i32, i32, i32), u8)>,
+) -> VecDeque<(i32, i32, i32)> {
+    let mut lit = VecDeque::new();
+
+    while let Some(((x, y, z), level)) = queue.pop_front() {
+        for &(dx, dy, dz) in NEIGHBORS.iter() {
+            let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+            let block = match volume.get_loaded_block(nx, ny, nz) {
+                Some(block) => block,
+                None => continue,
+            };
+
+            let neighbor_level = volume.get_light_level(light_type, nx, ny, nz);
+            if neighbor_level == 0 {
+                continue;
+            }
+
+            if neighbor_level <= get_spread_level(light_type, level, dy) {
+                // the neighbour may have been lit through this position, so it goes dark as well
+                volume.set_light_level(light_type, nx, ny, nz, 0);
+                queue.push_back(((nx, ny, nz), neighbor_level));
+
+                let emission = properties.get_emission(block);
+                if light_type == LightType::Block && emission > 0 {
+                    volume.set_light_level(light_type, nx, ny, nz, emission);
+                    lit.push_back((nx, ny, nz));
+                }
+            } else {
+                lit.push_back((nx, ny, nz));
+            }
+        }
+    }
+
+    lit
+}
+
+/// The level that light has after going one block further, `dy` being -1 when it goes down.
+fn get_spread_level(light_type: LightType, level: u8, dy: i32) -> u8 {
+    if light_type == LightType::Sky && level == MAX_LIGHT && dy == -1 {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
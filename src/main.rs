@@ -6,35 +6,103 @@ extern crate image;
 extern crate nalgebra;
 extern crate noise;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
+mod atlas;
+mod bench;
+mod biome;
+mod block_registry;
 mod camera;
+mod chunk;
+mod chunk_manager;
+mod fluid;
+mod frustum;
 mod game;
 mod graphics;
 mod input;
+mod lighting;
+mod mesher;
 mod nbt;
 mod object;
+mod occlusion;
 mod quaternion;
+mod renderer;
+mod save;
+mod tick;
+mod time;
 mod utils;
+mod vegetation;
+mod workers;
+mod worldgen;
 
+use atlas::*;
 use camera::*;
+use chunk_manager::*;
 use game::*;
 use graphics::*;
 use input::*;
+use mesher::BlockAppearance;
 use object::*;
+use renderer::*;
+use save::*;
+use time::*;
+use workers::*;
+use worldgen::*;
 //use nbt::*;
 //use quaternion::*;
 
 use nalgebra::Vector3;
+use std::sync::Arc;
 use std::time::Instant;
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--bench-meshing") {
+        bench::run_meshing_benchmark();
+        return;
+    }
+
     let mut player = Player::new();
     let mut camera = Camera::new(90);
     let mut input = Input::new();
-    let mut graphics = Graphics::new();
-    let mut blocks = Blocks::new();
+    let mut blocks = Blocks::load(BLOCKS_FILE)
+        .unwrap_or_else(|error| panic!("Failed to load {}: {}", BLOCKS_FILE, error));
+    let mut game = Game::new(8, &blocks);
+    let mut graphics = Graphics::new(GraphicsConfig::new(game.world.render_distance));
+    let save = WorldSave::open("saves/world").expect("failed to open the world save");
+    let mut level = match save.load_level().expect("failed to read level.dat") {
+        Some(level) => level,
+        None => {
+            let level = Level::new(rand::random::<u64>());
+            save.save_level(&level).expect("failed to write level.dat");
+            level
+        }
+    };
+    println!("World seed: {}", level.seed);
+    game.world.ticks.set_seed(level.seed);
+    game.world.time.set_ticks(level.time);
+    game.world.ticks.set_current_tick(level.tick);
+    if let Some(time_of_day) = get_argument("--time-of-day") {
+        let time_of_day = time_of_day
+            .parse()
+            .unwrap_or_else(|_| panic!("--time-of-day takes a number from 0 to 1, not {}", time_of_day));
+        game.world.time.set_time_of_day(time_of_day);
+    }
+    if std::env::args().any(|arg| arg == "--freeze-time") {
+        game.world.time.freeze();
+    }
+    let generator: Arc<TerrainGenerator> = Arc::new(DefaultTerrainGenerator::new(level.seed));
+    let workers = WorkerPool::new(
+        DEFAULT_WORKER_THREADS,
+        generator.clone(),
+        save.clone(),
+        game.world.get_light_properties(),
+    );
+    let mut chunk_manager = ChunkManager::new(game.world.render_distance, generator, save.clone());
 
-    camera.position = Vector3::new(32.0, 64.0, 32.0);
+    camera.position = Vector3::new(32.0, 80.0, 32.0);
     player.creative = true;
 
     let projection_matrix: [[f32; 4]; 4] = camera
@@ -46,18 +114,69 @@ fn main() {
     let mut cur_time = Instant::now();
 
     let skybox = Skybox;
+    let (atlas, tiles) = build_block_atlas(&blocks)
+        .unwrap_or_else(|error| panic!("Failed to build the block atlas: {}", error));
+    graphics.load_image_from_rgba("atlas", &atlas.image);
+    let mut world_renderer = WorldRenderer::new(BlockAppearance::new(&blocks, tiles), game.world.render_distance);
+    let mut tick_clock = TickClock::new();
 
     while !window_closed {
         prev_time = cur_time;
         cur_time = Instant::now();
-        let dt: f32 = (cur_time - prev_time).subsec_nanos() as f32 / 1_000_000_000.0;
+        let elapsed = cur_time - prev_time;
+        let dt: f32 = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+
+        for _ in 0..tick_clock.advance(dt) {
+            game.tick();
+        }
+
         let view_matrix: [[f32; 4]; 4] = camera.get_view_matrix().try_inverse().unwrap().into();
-        let graphics_params = GraphicsParams::new(view_matrix, projection_matrix);
+        let graphics_params = GraphicsParams::new(view_matrix, projection_matrix, game.world.time.get_sky());
 
-        graphics.start();
+        for result in workers.poll() {
+            match result {
+                JobResult::Loaded {
+                    chunk_x,
+                    chunk_z,
+                    chunk,
+                } => chunk_manager.receive_loaded_chunk(chunk_x, chunk_z, chunk),
+                JobResult::Meshed {
+                    chunk_x,
+                    chunk_z,
+                    meshes,
+                    connectivity,
+                } => world_renderer.receive_meshes(
+                    &mut graphics,
+                    &game.world,
+                    chunk_x,
+                    chunk_z,
+                    meshes,
+                    connectivity,
+                ),
+            }
+        }
+
+        chunk_manager.update(&mut game.world, &camera.position, &workers);
+        world_renderer.update(&mut game.world, &workers, &camera.position);
+
+        graphics.start(&graphics_params.sky);
 
         skybox.draw(&mut graphics, &graphics_params);
+        world_renderer.draw(&mut graphics, &graphics_params);
 
         graphics.finish();
     }
+
+    chunk_manager.save_all(&game.world);
+    level.time = game.world.time.get_ticks();
+    level.tick = game.world.ticks.get_current_tick();
+    if let Err(error) = save.save_level(&level) {
+        println!("Failed to save level.dat: {}", error);
+    }
+}
+
+/// The value following `name` on the command line, e.g. `0.25` for `--time-of-day 0.25`.
+fn get_argument(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next().and_then(|_| args.next())
 }
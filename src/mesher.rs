@@ -0,0 +1,990 @@
+use atlas::{AtlasRect, BlockTiles};
+use chunk::BlockData;
+use fluid::{get_fluid_height, Fluid};
+use game::{Blocks, Chunk, RenderLayer, World, RENDER_LAYERS};
+use lighting::{LightType, MAX_LIGHT};
+use std::sync::Arc;
+
+/// The direction each face of a block points in, in the same order as the faces of
+/// `Block::get_vertex_buffer` and `Block::textures`.
+pub const FACE_NORMALS: [[i32; 3]; 6] = [
+    [0, 0, 1],  // back
+    [0, 1, 0],  // top
+    [0, 0, -1], // front
+    [0, -1, 0], // bottom
+    [1, 0, 0],  // right
+    [-1, 0, 0], // left
+];
+
+// the corners of each face relative to the block's center and their texture coordinates, wound
+// like `Block::get_vertex_buffer`
+const FACE_CORNERS: [[([f32; 3], [f32; 2]); 4]; 6] = [
+    [
+        ([-0.5, -0.5, 0.5], [0.0, 1.0]),
+        ([0.5, -0.5, 0.5], [1.0, 1.0]),
+        ([-0.5, 0.5, 0.5], [0.0, 0.0]),
+        ([0.5, 0.5, 0.5], [1.0, 0.0]),
+    ],
+    [
+        ([-0.5, 0.5, 0.5], [0.0, 0.0]),
+        ([0.5, 0.5, 0.5], [1.0, 0.0]),
+        ([-0.5, 0.5, -0.5], [0.0, 1.0]),
+        ([0.5, 0.5, -0.5], [1.0, 1.0]),
+    ],
+    [
+        ([-0.5, 0.5, -0.5], [0.0, 0.0]),
+        ([0.5, 0.5, -0.5], [1.0, 0.0]),
+        ([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        ([0.5, -0.5, -0.5], [1.0, 1.0]),
+    ],
+    [
+        ([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        ([0.5, -0.5, -0.5], [1.0, 1.0]),
+        ([-0.5, -0.5, 0.5], [0.0, 0.0]),
+        ([0.5, -0.5, 0.5], [1.0, 0.0]),
+    ],
+    [
+        ([0.5, -0.5, 0.5], [0.0, 1.0]),
+        ([0.5, -0.5, -0.5], [1.0, 1.0]),
+        ([0.5, 0.5, 0.5], [0.0, 0.0]),
+        ([0.5, 0.5, -0.5], [1.0, 0.0]),
+    ],
+    [
+        ([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        ([-0.5, -0.5, 0.5], [1.0, 1.0]),
+        ([-0.5, 0.5, -0.5], [0.0, 0.0]),
+        ([-0.5, 0.5, 0.5], [1.0, 0.0]),
+    ],
+];
+
+// the axes that the texture's u and v coordinates run along on each face
+const FACE_UV_AXES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 1), (0, 2), (2, 1), (2, 1)];
+
+const FACE_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+// the same quad split along the other diagonal
+const FLIPPED_FACE_INDICES: [u32; 6] = [0, 1, 3, 0, 3, 2];
+
+// how much darker a corner gets for each block around it that occludes it
+const OCCLUSION_STRENGTH: f32 = 0.2;
+
+/// The positions of the chunks around a chunk, in the order of `ChunkNeighborhood::neighbors`.
+pub const NEIGHBOR_CHUNK_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// The index in `NEIGHBOR_CHUNK_OFFSETS` of the offset `(dx, dz)`, stored at `(dx + 1) * 3 + dz + 1`.
+/// The center has no neighbour index, so its entry is never read.
+const NEIGHBOR_INDICES: [usize; 9] = [7, 1, 6, 3, 0, 2, 5, 0, 4];
+
+/// The coarsest level of detail, where a cell is a whole section.
+pub const MAX_DETAIL_LEVEL: u8 = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeshingMode {
+    /// One quad for every exposed block face.
+    Naive,
+    /// Merges neighbouring exposed faces of the same block into larger quads.
+    Greedy,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockVertex {
+    pub position: [f32; 3],
+    /// Texture coordinates in blocks, so they go past 1 on quads that span several blocks and the
+    /// texture repeats across them.
+    pub uv: [f32; 2],
+    /// The texture's rectangle in the atlas: its origin followed by its size.
+    pub tile: [f32; 4],
+    /// The sky and block light at the corner, from 0 to 1.
+    pub light: [f32; 2],
+    /// How much the corner is darkened by the blocks around it, from 1 for no darkening.
+    pub occlusion: f32,
+}
+
+/// A block face along with how each of its corners is lit. The greedy mesher only merges faces
+/// that are equal, so that merged quads look the same as the faces they replace.
+#[derive(Copy, Clone, PartialEq)]
+struct ShadedFace {
+    block: u16,
+    /// The sky and block light of each corner, averaged over the blocks in front of the face
+    /// that touch the corner.
+    light: [[f32; 2]; 4],
+    /// The number of opaque blocks in front of the face that touch each corner, from 0 to 3.
+    occlusion: [u8; 4],
+}
+
+/// How every block is drawn: the atlas rectangles of its faces, its render layer and whether the
+/// faces of its neighbours show through it. Built once, then shared with the mesh workers.
+#[derive(Clone)]
+pub struct BlockAppearance {
+    tiles: BlockTiles,
+    layers: Vec<RenderLayer>,
+    transparent: Vec<bool>,
+}
+
+impl BlockAppearance {
+    pub fn new(blocks: &Blocks, tiles: BlockTiles) -> BlockAppearance {
+        BlockAppearance {
+            tiles: tiles,
+            layers: blocks
+                .block_map
+                .iter()
+                .map(|block| block.as_ref().map_or(RenderLayer::Opaque, |block| block.layer))
+                .collect(),
+            transparent: blocks
+                .block_map
+                .iter()
+                .map(|block| block.as_ref().map_or(true, |block| block.transparent))
+                .collect(),
+        }
+    }
+
+    pub fn get_tile(&self, block: u16, face: usize) -> AtlasRect {
+        self.tiles.get(block, face)
+    }
+
+    pub fn get_layer(&self, block: u16) -> RenderLayer {
+        self.layers[block as usize]
+    }
+
+    /// Whether a block hides what's behind it, and so casts ambient occlusion.
+    pub fn is_opaque(&self, block: u16) -> bool {
+        !self.transparent[block as usize]
+    }
+
+    /// Whether the face of `block` that touches `neighbor` can be seen.
+    pub fn is_face_visible(&self, block: u16, neighbor: u16) -> bool {
+        if neighbor == 0 {
+            return true;
+        }
+
+        // the faces between two blocks of the same glass would only make it look dirty, but the
+        // leaves inside of a tree should still show through the holes of the outer ones
+        self.transparent[neighbor as usize]
+            && !(neighbor == block && self.get_layer(block) == RenderLayer::Translucent)
+    }
+}
+
+/// The visible block faces of one render layer of a chunk, ready to be uploaded to the GPU.
+pub struct ChunkMesh {
+    pub vertices: Vec<BlockVertex>,
+    pub indices: Vec<u32>,
+    /// Where the indices of each section start, so that sections can be drawn on their own.
+    pub section_starts: Vec<usize>,
+}
+
+impl ChunkMesh {
+    pub fn new() -> ChunkMesh {
+        ChunkMesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            section_starts: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// The indices with the quads ordered from the furthest from `eye` to the nearest, so that
+    /// blending them in that order shows the nearer quads in front.
+    pub fn get_sorted_indices(&self, eye: [f32; 3]) -> Vec<u32> {
+        let mut quads: Vec<(f32, u32)> = self
+            .vertices
+            .chunks(4)
+            .enumerate()
+            .map(|(quad, corners)| {
+                let mut distance = 0.0;
+                for axis in 0..3 {
+                    let center = (corners[0].position[axis] + corners[3].position[axis]) / 2.0;
+                    distance += (center - eye[axis]) * (center - eye[axis]);
+                }
+
+                (distance, quad as u32 * 4)
+            })
+            .collect();
+        quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (_, first_index) in quads {
+            let first = first_index as usize / 4 * 6;
+            indices.extend_from_slice(&self.indices[first..first + 6]);
+        }
+
+        indices
+    }
+
+    /// Adds a quad covering the given face of every block from `position` up to `position + size`.
+    /// The size along the face's normal is ignored.
+    fn push_quad(
+        &mut self,
+        position: [f32; 3],
+        size: [f32; 3],
+        face: usize,
+        tile: AtlasRect,
+        shading: &ShadedFace,
+    ) {
+        let first_index = self.vertices.len() as u32;
+        let (u_axis, v_axis) = FACE_UV_AXES[face];
+
+        for (corner_index, &(offset, uv)) in FACE_CORNERS[face].iter().enumerate() {
+            let mut corner = [0.0; 3];
+            for axis in 0..3 {
+                corner[axis] = if FACE_NORMALS[face][axis] != 0 {
+                    position[axis] + offset[axis]
+                } else {
+                    // stretch the unit face from the first block to the last one
+                    position[axis] - 0.5 + (offset[axis] + 0.5) * size[axis]
+                };
+            }
+
+            self.vertices.push(BlockVertex {
+                position: corner,
+                uv: [uv[0] * size[u_axis], uv[1] * size[v_axis]],
+                tile: tile,
+                light: shading.light[corner_index],
+                occlusion: 1.0 - shading.occlusion[corner_index] as f32 * OCCLUSION_STRENGTH,
+            });
+        }
+
+        // split the quad along the diagonal whose corners are occluded alike, otherwise a single
+        // dark corner bleeds across the whole quad on one of its triangles
+        let occlusion = shading.occlusion;
+        let indices = if occlusion[1] + occlusion[2] > occlusion[0] + occlusion[3] {
+            &FLIPPED_FACE_INDICES
+        } else {
+            &FACE_INDICES
+        };
+
+        for index in indices {
+            self.indices.push(first_index + index);
+        }
+    }
+}
+
+/// A chunk's mesh for every render layer.
+pub struct ChunkMeshes {
+    meshes: [ChunkMesh; 3],
+}
+
+impl ChunkMeshes {
+    pub fn new() -> ChunkMeshes {
+        ChunkMeshes {
+            meshes: [ChunkMesh::new(), ChunkMesh::new(), ChunkMesh::new()],
+        }
+    }
+
+    pub fn get(&self, layer: RenderLayer) -> &ChunkMesh {
+        &self.meshes[layer as usize]
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        RENDER_LAYERS.iter().map(|layer| self.get(*layer).get_triangle_count()).sum()
+    }
+
+    pub fn get_vertex_count(&self) -> usize {
+        RENDER_LAYERS.iter().map(|layer| self.get(*layer).vertices.len()).sum()
+    }
+
+    /// The smallest box around every vertex of every layer as its lowest and highest corner, or
+    /// `None` if the meshes are empty.
+    pub fn get_bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut vertices = self.meshes.iter().flat_map(|mesh| mesh.vertices.iter());
+        let first = match vertices.next() {
+            Some(vertex) => vertex.position,
+            None => return None,
+        };
+
+        let (mut min, mut max) = (first, first);
+        for vertex in vertices {
+            for axis in 0..3 {
+                min[axis] = f32::min(min[axis], vertex.position[axis]);
+                max[axis] = f32::max(max[axis], vertex.position[axis]);
+            }
+        }
+
+        Some((min, max))
+    }
+
+    /// Starts the faces of the next section. Meshers call this for every section from the bottom
+    /// up, including empty ones, before pushing any of its quads.
+    fn start_section(&mut self) {
+        for mesh in self.meshes.iter_mut() {
+            let start = mesh.indices.len();
+            mesh.section_starts.push(start);
+        }
+    }
+
+    pub fn into_translucent(self) -> ChunkMesh {
+        let ChunkMeshes {
+            meshes: [_, _, translucent],
+        } = self;
+
+        translucent
+    }
+
+    fn push_quad(
+        &mut self,
+        position: [f32; 3],
+        size: [f32; 3],
+        face: usize,
+        shading: &ShadedFace,
+        appearance: &BlockAppearance,
+    ) {
+        self.meshes[appearance.get_layer(shading.block) as usize].push_quad(
+            position,
+            size,
+            face,
+            appearance.get_tile(shading.block, face),
+            shading,
+        );
+    }
+}
+
+/// A chunk along with the eight chunks around it, which decide whether the faces on its border are
+/// exposed and how their corners are shaded. Neighbours that aren't loaded count as air.
+pub struct ChunkNeighborhood<'a> {
+    pub center: &'a Chunk,
+    /// The chunks at the offsets in `NEIGHBOR_CHUNK_OFFSETS`: first the ones at +x, -x, +z and -z,
+    /// then the diagonal ones.
+    pub neighbors: [Option<&'a Chunk>; 8],
+}
+
+impl<'a> ChunkNeighborhood<'a> {
+    pub fn new(center: &'a Chunk, neighbors: &'a [Option<Arc<Chunk>>]) -> ChunkNeighborhood<'a> {
+        let mut neighborhood = ChunkNeighborhood {
+            center: center,
+            neighbors: [None; 8],
+        };
+        for (neighbor, chunk) in neighborhood.neighbors.iter_mut().zip(neighbors) {
+            *neighbor = chunk.as_ref().map(|chunk| &**chunk);
+        }
+
+        neighborhood
+    }
+
+    pub fn from_world(world: &'a World, chunk_x: i32, chunk_z: i32) -> Option<ChunkNeighborhood<'a>> {
+        world.get_chunk(chunk_x, chunk_z).map(|center| {
+            let mut neighborhood = ChunkNeighborhood {
+                center: center,
+                neighbors: [None; 8],
+            };
+            for (index, &(dx, dz)) in NEIGHBOR_CHUNK_OFFSETS.iter().enumerate() {
+                neighborhood.neighbors[index] = world.get_chunk(chunk_x + dx, chunk_z + dz);
+            }
+
+            neighborhood
+        })
+    }
+
+    /// Gets a block relative to the center chunk. x and z may reach into the neighbours.
+    pub fn get_block_id(&self, x: i32, y: i32, z: i32) -> u16 {
+        self.get_block_data(x, y, z).id
+    }
+
+    pub fn get_block_data(&self, x: i32, y: i32, z: i32) -> BlockData {
+        if y < 0 || y > 255 {
+            return BlockData::air();
+        }
+
+        match self.get_chunk(x, z) {
+            Some(chunk) => chunk.get_block_data((x & 15) as usize, y as usize, (z & 15) as usize),
+            None => BlockData::air(),
+        }
+    }
+
+    /// How high the given fluid fills a block, or `None` if the block isn't that fluid. Fluid with
+    /// the same fluid above it is full, so that falling fluid joins up with what it falls into.
+    fn get_fluid_height(&self, fluid: u16, x: i32, y: i32, z: i32) -> Option<f32> {
+        let block = self.get_block_data(x, y, z);
+        if block.id != fluid {
+            None
+        } else if self.get_block_id(x, y + 1, z) == fluid {
+            Some(1.0)
+        } else {
+            Some(get_fluid_height(block.state))
+        }
+    }
+
+    /// The sky and block light of a block relative to the center chunk, like `World::get_light`.
+    pub fn get_light(&self, x: i32, y: i32, z: i32) -> [u8; 2] {
+        if y < 0 {
+            return [0, 0];
+        } else if y > 255 {
+            return [MAX_LIGHT, 0];
+        }
+
+        match self.get_chunk(x, z) {
+            Some(chunk) => {
+                let (x, y, z) = ((x & 15) as usize, y as usize, (z & 15) as usize);
+                [chunk.light.get(LightType::Sky, x, y, z), chunk.light.get(LightType::Block, x, y, z)]
+            }
+            None => [MAX_LIGHT, 0],
+        }
+    }
+
+    fn get_chunk(&self, x: i32, z: i32) -> Option<&'a Chunk> {
+        let (dx, dz) = (x >> 4, z >> 4);
+        if (dx, dz) == (0, 0) {
+            return Some(self.center);
+        }
+        if dx.abs() > 1 || dz.abs() > 1 {
+            return None;
+        }
+
+        self.neighbors[NEIGHBOR_INDICES[((dx + 1) * 3 + dz + 1) as usize]]
+    }
+
+    /// The face of a block that points in the direction of `FACE_NORMALS[face]`, with the
+    /// ambient occlusion and smooth light of its corners. Each corner is shaded by the block in
+    /// front of the face and the three blocks next to that one which touch the corner.
+    fn get_shaded_face(
+        &self,
+        appearance: &BlockAppearance,
+        block: u16,
+        position: [i32; 3],
+        face: usize,
+    ) -> ShadedFace {
+        let normal = FACE_NORMALS[face];
+        let (u_axis, v_axis) = FACE_UV_AXES[face];
+        let front = [position[0] + normal[0], position[1] + normal[1], position[2] + normal[2]];
+
+        let mut shading = ShadedFace {
+            block: block,
+            light: [[0.0; 2]; 4],
+            occlusion: [0; 4],
+        };
+
+        for (corner, &(offset, _)) in FACE_CORNERS[face].iter().enumerate() {
+            let mut side_u = front;
+            side_u[u_axis] += if offset[u_axis] > 0.0 { 1 } else { -1 };
+            let mut side_v = front;
+            side_v[v_axis] += if offset[v_axis] > 0.0 { 1 } else { -1 };
+            let mut diagonal = side_u;
+            diagonal[v_axis] = side_v[v_axis];
+
+            let is_opaque = |p: [i32; 3]| appearance.is_opaque(self.get_block_id(p[0], p[1], p[2]));
+            let (opaque_u, opaque_v) = (is_opaque(side_u), is_opaque(side_v));
+            // with both sides blocked, the diagonal block can't be seen from the corner at all
+            let opaque_diagonal = (opaque_u && opaque_v) || is_opaque(diagonal);
+
+            shading.occlusion[corner] = opaque_u as u8 + opaque_v as u8 + opaque_diagonal as u8;
+
+            // opaque blocks have no light of their own, so they're left out of the average
+            let samples = [
+                (front, false),
+                (side_u, opaque_u),
+                (side_v, opaque_v),
+                (diagonal, opaque_diagonal),
+            ];
+            let mut sum = [0.0; 2];
+            let mut count = 0.0;
+            for &(sample, _) in samples.iter().filter(|&&(_, opaque)| !opaque) {
+                let light = self.get_light(sample[0], sample[1], sample[2]);
+                sum[0] += light[0] as f32;
+                sum[1] += light[1] as f32;
+                count += 1.0;
+            }
+
+            let scale = count * MAX_LIGHT as f32;
+            shading.light[corner] = [sum[0] / scale, sum[1] / scale];
+        }
+
+        shading
+    }
+
+    /// The face of a cell of `size` blocks from `origin` in a coarser mesh, evenly lit by the
+    /// brightest of the blocks in front of it. Ambient occlusion is left out, at the distance these
+    /// meshes are drawn at it would hardly show.
+    fn get_shaded_cell_face(&self, block: u16, origin: [i32; 3], size: i32, face: usize) -> ShadedFace {
+        let normal = FACE_NORMALS[face];
+        let (u_axis, v_axis) = FACE_UV_AXES[face];
+
+        let mut front = origin;
+        for axis in 0..3 {
+            front[axis] += if normal[axis] > 0 { size } else { normal[axis] };
+        }
+
+        let mut light = [0; 2];
+        for u in 0..size {
+            for v in 0..size {
+                let mut sample = front;
+                sample[u_axis] += u;
+                sample[v_axis] += v;
+
+                let sample_light = self.get_light(sample[0], sample[1], sample[2]);
+                light[0] = light[0].max(sample_light[0]);
+                light[1] = light[1].max(sample_light[1]);
+            }
+        }
+
+        let light = [light[0] as f32 / MAX_LIGHT as f32, light[1] as f32 / MAX_LIGHT as f32];
+        ShadedFace {
+            block: block,
+            light: [light; 4],
+            occlusion: [0; 4],
+        }
+    }
+
+    /// The block a cell of `size` blocks from `origin` is drawn as in a coarser mesh: air if at
+    /// least half of it is air, otherwise its most common block. Ties go to the block found first
+    /// from the top down, so that the surface keeps its top blocks.
+    fn get_cell_block(&self, origin: [i32; 3], size: i32) -> u16 {
+        let mut counts: Vec<(u16, i32)> = Vec::new();
+        let mut air = 0;
+
+        for y in (origin[1]..origin[1] + size).rev() {
+            for z in origin[2]..origin[2] + size {
+                for x in origin[0]..origin[0] + size {
+                    let block = self.get_block_id(x, y, z);
+                    if block == 0 {
+                        air += 1;
+                    } else if let Some(count) = counts.iter_mut().find(|count| count.0 == block) {
+                        count.1 += 1;
+                    } else {
+                        counts.push((block, 1));
+                    }
+                }
+            }
+        }
+
+        if air * 2 >= size * size * size {
+            return 0;
+        }
+
+        let mut most_common = counts[0];
+        for &count in &counts[1..] {
+            if count.1 > most_common.1 {
+                most_common = count;
+            }
+        }
+
+        most_common.0
+    }
+}
+
+/// Builds the meshes of every visible block face in the center chunk, positioned in world space.
+pub fn build_chunk_mesh(
+    neighborhood: &ChunkNeighborhood,
+    chunk_x: i32,
+    chunk_z: i32,
+    appearance: &BlockAppearance,
+    mode: MeshingMode,
+) -> ChunkMeshes {
+    match mode {
+        MeshingMode::Naive => build_naive_mesh(neighborhood, chunk_x, chunk_z, appearance),
+        MeshingMode::Greedy => build_greedy_mesh(neighborhood, chunk_x, chunk_z, appearance),
+    }
+}
+
+/// Builds a coarser mesh of the center chunk for drawing it far away. At each level of detail above
+/// 0 the resolution halves: the chunk is split into cells of `2^level` blocks along every axis,
+/// which are meshed like blocks as if each cell was one big block. Neighbours that aren't given
+/// count as air, which closes off the chunk's side towards them with walls.
+pub fn build_lod_mesh(
+    neighborhood: &ChunkNeighborhood,
+    chunk_x: i32,
+    chunk_z: i32,
+    appearance: &BlockAppearance,
+    level: u8,
+) -> ChunkMeshes {
+    let size = 1 << level.min(MAX_DETAIL_LEVEL);
+    let cells = 16 / size;
+    let height = 256 / size;
+
+    // the cells of the chunk along with the ones next to its sides in the neighbours
+    let width = cells + 2;
+    let index = |x: i32, y: i32, z: i32| ((y * width + z + 1) * width + x + 1) as usize;
+    let mut grid = vec![0; (width * width * height) as usize];
+    for y in 0..height {
+        for z in -1..cells + 1 {
+            for x in -1..cells + 1 {
+                if (x < 0 || x >= cells) && (z < 0 || z >= cells) {
+                    continue;
+                }
+
+                grid[index(x, y, z)] = neighborhood.get_cell_block([x * size, y * size, z * size], size);
+            }
+        }
+    }
+
+    let mut mesh = ChunkMeshes::new();
+
+    for section in 0..16 {
+        mesh.start_section();
+
+        for y in section * cells..(section + 1) * cells {
+            for z in 0..cells {
+                for x in 0..cells {
+                    let block = grid[index(x, y, z)];
+                    if block == 0 {
+                        continue;
+                    }
+
+                    for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                        let neighbor_y = y + normal[1];
+                        let neighbor = if neighbor_y < 0 || neighbor_y >= height {
+                            0
+                        } else {
+                            grid[index(x + normal[0], neighbor_y, z + normal[2])]
+                        };
+                        if !appearance.is_face_visible(block, neighbor) {
+                            continue;
+                        }
+
+                        let origin = [x * size, y * size, z * size];
+                        let shading = neighborhood.get_shaded_cell_face(block, origin, size, face);
+
+                        // quads are placed by their first block, so the faces on the far side of
+                        // the cell are moved over to its last block
+                        let mut position = [
+                            (chunk_x * 16 + origin[0]) as f32,
+                            origin[1] as f32,
+                            (chunk_z * 16 + origin[2]) as f32,
+                        ];
+                        for axis in 0..3 {
+                            if normal[axis] > 0 {
+                                position[axis] += (size - 1) as f32;
+                            }
+                        }
+
+                        mesh.push_quad(position, [size as f32; 3], face, &shading, appearance);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+fn build_naive_mesh(
+    neighborhood: &ChunkNeighborhood,
+    chunk_x: i32,
+    chunk_z: i32,
+    appearance: &BlockAppearance,
+) -> ChunkMeshes {
+    let mut mesh = ChunkMeshes::new();
+
+    for y in 0..256 {
+        if y % 16 == 0 {
+            mesh.start_section();
+        }
+
+        for z in 0..16 {
+            for x in 0..16 {
+                let block = neighborhood.center.get_block(x as usize, y as usize, z as usize);
+                if block == 0 {
+                    continue;
+                }
+
+                if Fluid::from_block(block).is_some() {
+                    push_fluid_faces(&mut mesh, neighborhood, appearance, chunk_x, chunk_z, [x, y, z]);
+                    continue;
+                }
+
+                let position = [
+                    (chunk_x * 16 + x) as f32,
+                    y as f32,
+                    (chunk_z * 16 + z) as f32,
+                ];
+
+                for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                    let neighbor = neighborhood.get_block_id(x + normal[0], y + normal[1], z + normal[2]);
+                    if appearance.is_face_visible(block, neighbor) {
+                        let shading = neighborhood.get_shaded_face(appearance, block, [x, y, z], face);
+                        mesh.push_quad(position, [1.0; 3], face, &shading, appearance);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Meshes the chunk 16x16x16 blocks at a time. Each slice of a section along a face's normal is
+/// turned into a mask of the exposed faces, which is then covered with as few rectangles of the
+/// same block and shading as the greedy algorithm finds: grow a rectangle along one axis as far as
+/// the face repeats, then along the other axis while whole rows match.
+fn build_greedy_mesh(
+    neighborhood: &ChunkNeighborhood,
+    chunk_x: i32,
+    chunk_z: i32,
+    appearance: &BlockAppearance,
+) -> ChunkMeshes {
+    let mut mesh = ChunkMeshes::new();
+    let mut mask: [Option<ShadedFace>; 16 * 16] = [None; 16 * 16];
+
+    for section in 0..16 {
+        mesh.start_section();
+        if neighborhood.center.get_section(section as usize).is_none() {
+            continue;
+        }
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            let normal_axis = normal.iter().position(|component| *component != 0).unwrap();
+            let a_axis = (normal_axis + 1) % 3;
+            let b_axis = (normal_axis + 2) % 3;
+
+            for slice in 0..16 {
+                for a in 0..16 {
+                    for b in 0..16 {
+                        let mut position = [0; 3];
+                        position[normal_axis] = slice;
+                        position[a_axis] = a;
+                        position[b_axis] = b;
+                        position[1] += section * 16;
+
+                        // fluids have shapes of their own, they're added below
+                        let block = neighborhood.get_block_id(position[0], position[1], position[2]);
+                        let exposed = block != 0
+                            && Fluid::from_block(block).is_none()
+                            && appearance.is_face_visible(
+                                block,
+                                neighborhood.get_block_id(
+                                    position[0] + normal[0],
+                                    position[1] + normal[1],
+                                    position[2] + normal[2],
+                                ),
+                            );
+
+                        mask[(a * 16 + b) as usize] = if exposed {
+                            Some(neighborhood.get_shaded_face(appearance, block, position, face))
+                        } else {
+                            None
+                        };
+                    }
+                }
+
+                for a in 0..16 {
+                    let mut b = 0;
+                    while b < 16 {
+                        let exposed = match mask[(a * 16 + b) as usize] {
+                            Some(exposed) => exposed,
+                            None => {
+                                b += 1;
+                                continue;
+                            }
+                        };
+
+                        let mut height = 1;
+                        while b + height < 16 && mask[(a * 16 + b + height) as usize] == Some(exposed) {
+                            height += 1;
+                        }
+
+                        let mut width = 1;
+                        'grow: while a + width < 16 {
+                            for k in 0..height {
+                                if mask[((a + width) * 16 + b + k) as usize] != Some(exposed) {
+                                    break 'grow;
+                                }
+                            }
+                            width += 1;
+                        }
+
+                        for da in 0..width {
+                            for db in 0..height {
+                                mask[((a + da) * 16 + b + db) as usize] = None;
+                            }
+                        }
+
+                        let mut position = [0.0; 3];
+                        position[normal_axis] = slice as f32;
+                        position[a_axis] = a as f32;
+                        position[b_axis] = b as f32;
+                        position[0] += (chunk_x * 16) as f32;
+                        position[1] += (section * 16) as f32;
+                        position[2] += (chunk_z * 16) as f32;
+
+                        let mut size = [1.0; 3];
+                        size[a_axis] = width as f32;
+                        size[b_axis] = height as f32;
+
+                        mesh.push_quad(position, size, face, &exposed, appearance);
+                        b += height;
+                    }
+                }
+            }
+        }
+
+        for y in section * 16..(section + 1) * 16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    if Fluid::from_block(neighborhood.get_block_id(x, y, z)).is_some() {
+                        push_fluid_faces(&mut mesh, neighborhood, appearance, chunk_x, chunk_z, [x, y, z]);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Adds the faces of a fluid block. Its top is lowered to its level unless the same fluid is above
+/// it, and the faces between two blocks of the same fluid are left out, except for the part of a
+/// side that sticks out above a lower neighbour.
+fn push_fluid_faces(
+    mesh: &mut ChunkMeshes,
+    neighborhood: &ChunkNeighborhood,
+    appearance: &BlockAppearance,
+    chunk_x: i32,
+    chunk_z: i32,
+    position: [i32; 3],
+) {
+    let (x, y, z) = (position[0], position[1], position[2]);
+    let block = neighborhood.get_block_id(x, y, z);
+    let height = neighborhood.get_fluid_height(block, x, y, z).unwrap();
+
+    for (face, normal) in FACE_NORMALS.iter().enumerate() {
+        let (nx, ny, nz) = (x + normal[0], y + normal[1], z + normal[2]);
+        let neighbor = neighborhood.get_block_id(nx, ny, nz);
+
+        // the part of the face that is shown, from the bottom of the block up
+        let (bottom, top) = match face {
+            1 if neighbor == block => continue,
+            1 => (height, height),
+            3 if neighbor == block || !appearance.is_face_visible(block, neighbor) => continue,
+            3 => (0.0, 0.0),
+            _ => match neighborhood.get_fluid_height(block, nx, ny, nz) {
+                Some(neighbor_height) if neighbor_height >= height => continue,
+                Some(neighbor_height) => (neighbor_height, height),
+                None if !appearance.is_face_visible(block, neighbor) => continue,
+                None => (0.0, height),
+            },
+        };
+
+        // quads are stretched up from the bottom of a block, so the block is moved up to where the
+        // face starts, and the top face, which sits half a block above, down to the surface
+        let mut quad_position = [(chunk_x * 16 + x) as f32, y as f32 + bottom, (chunk_z * 16 + z) as f32];
+        if face == 1 {
+            quad_position[1] -= 1.0;
+        }
+
+        let shading = neighborhood.get_shaded_face(appearance, block, position, face);
+        mesh.push_quad(quad_position, [1.0, top - bottom, 1.0], face, &shading, appearance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas::pack_textures;
+    use game::{create_chunk, BlockType};
+    use image::RgbaImage;
+
+    fn create_appearance() -> BlockAppearance {
+        let blocks = Blocks::from_json(include_str!("../data/blocks.json")).unwrap();
+        let mut textures: Vec<String> = blocks
+            .block_map
+            .iter()
+            .filter_map(|block| block.as_ref())
+            .flat_map(|block| block.textures.clone())
+            .collect();
+        textures.sort();
+        textures.dedup();
+
+        let atlas = pack_textures(
+            textures
+                .into_iter()
+                .map(|texture| (texture, RgbaImage::new(16, 16)))
+                .collect(),
+            0,
+        );
+        let tiles = atlas.get_block_tiles(&blocks).unwrap();
+
+        BlockAppearance::new(&blocks, tiles)
+    }
+
+    // the number of quads in the opaque layer of the center chunk's mesh
+    fn count_quads(center: &Chunk, neighbors: &[Option<Arc<Chunk>>], mode: MeshingMode) -> usize {
+        let appearance = create_appearance();
+        let neighborhood = ChunkNeighborhood::new(center, neighbors);
+        let meshes = build_chunk_mesh(&neighborhood, 0, 0, &appearance, mode);
+
+        meshes.get(RenderLayer::Opaque).get_triangle_count() / 2
+    }
+
+    fn no_neighbors() -> Vec<Option<Arc<Chunk>>> {
+        vec![None; 8]
+    }
+
+    #[test]
+    fn test_single_block() {
+        let mut chunk = create_chunk();
+        chunk.set_block(8, 64, 8, BlockType::Stone as u16);
+
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Naive), 6);
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Greedy), 6);
+    }
+
+    #[test]
+    fn test_adjacent_blocks_hide_shared_faces() {
+        let mut chunk = create_chunk();
+        chunk.set_block(8, 64, 8, BlockType::Stone as u16);
+        chunk.set_block(9, 64, 8, BlockType::Stone as u16);
+
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Naive), 10);
+        // the four long sides are merged into one quad each
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Greedy), 6);
+    }
+
+    #[test]
+    fn test_border_culled_against_neighbor() {
+        let mut chunk = create_chunk();
+        chunk.set_block(15, 64, 8, BlockType::Stone as u16);
+
+        // the +x neighbour comes first in `NEIGHBOR_CHUNK_OFFSETS`
+        let mut neighbor = create_chunk();
+        neighbor.set_block(0, 64, 8, BlockType::Stone as u16);
+        let mut neighbors = no_neighbors();
+        neighbors[0] = Some(Arc::new(neighbor));
+
+        assert_eq!(count_quads(&chunk, &neighbors, MeshingMode::Naive), 5);
+        assert_eq!(count_quads(&chunk, &neighbors, MeshingMode::Greedy), 5);
+
+        // a neighbour that isn't loaded counts as air
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Naive), 6);
+    }
+
+    #[test]
+    fn test_greedy_merges_flat_area() {
+        let mut chunk = create_chunk();
+        for x in 4..8 {
+            for z in 4..8 {
+                chunk.set_block(x, 64, z, BlockType::Stone as u16);
+            }
+        }
+
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Naive), 16 + 16 + 4 * 4);
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Greedy), 6);
+    }
+
+    #[test]
+    fn test_greedy_keeps_different_blocks_apart() {
+        let mut chunk = create_chunk();
+        chunk.set_block(8, 64, 8, BlockType::Stone as u16);
+        chunk.set_block(9, 64, 8, BlockType::Dirt as u16);
+
+        assert_eq!(count_quads(&chunk, &no_neighbors(), MeshingMode::Greedy), 10);
+    }
+
+    #[test]
+    fn test_neighbor_indices_match_offsets() {
+        for (index, &(dx, dz)) in NEIGHBOR_CHUNK_OFFSETS.iter().enumerate() {
+            assert_eq!(NEIGHBOR_INDICES[((dx + 1) * 3 + dz + 1) as usize], index);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use bytebuffer::ByteBuffer;
+use chunk::{check_remaining, invalid_data};
 use std::collections::HashMap;
+use std::io;
 
 pub struct Nbt {
     strings: HashMap<String, String>,
@@ -7,31 +9,39 @@ pub struct Nbt {
 }
 
 impl Nbt {
-    pub fn from_buffer(mut buf: ByteBuffer) -> Nbt {
+    pub fn new() -> Nbt {
+        Nbt {
+            strings: HashMap::new(),
+            ints: HashMap::new()
+        }
+    }
+
+    /// Reads the tags written by `to_buffer`, failing if the data is cut off or corrupt.
+    pub fn from_buffer(mut buf: ByteBuffer) -> io::Result<Nbt> {
         let mut nbt = Nbt {
             strings: HashMap::new(),
             ints: HashMap::new()
         };
 
+        check_remaining(&buf, 1)?;
         let tags = buf.read_u8();
         for _ in 0..tags {
+            check_remaining(&buf, 1)?;
             let tag_type = buf.read_u8();
-            let strlen = buf.read_u8();
-            let bytes = buf.read_bytes(strlen as usize);
-            let tag_name = String::from_utf8(bytes).unwrap();
+            let tag_name = read_string(&mut buf)?;
 
             if tag_type == 0 {
-                let vallen = buf.read_u8();
-                let val_bytes = buf.read_bytes(vallen as usize);
-                let val = String::from_utf8(val_bytes).unwrap();
-
+                let val = read_string(&mut buf)?;
                 nbt.strings.insert(tag_name, val);
             } else if tag_type == 1 {
+                check_remaining(&buf, 4)?;
                 nbt.ints.insert(tag_name, buf.read_i32());
+            } else {
+                return Err(invalid_data(format!("tag {} has unknown type {}", tag_name, tag_type)));
             }
         }
 
-        nbt
+        Ok(nbt)
     }
 
     pub fn to_buffer(&self, buf: &mut ByteBuffer) {
@@ -53,11 +63,28 @@ impl Nbt {
         }
     }
 
+    pub fn set_string(&mut self, tag: &str, value: &str) {
+        self.strings.insert(tag.to_string(), value.to_string());
+    }
+
     pub fn get_string(&self, tag: &String) -> &String {
         self.strings.get(tag).unwrap()
     }
 
+    /// Like `get_string`, but `None` if the tag isn't there.
+    pub fn find_string(&self, tag: &str) -> Option<&String> {
+        self.strings.get(tag)
+    }
+
     pub fn get_i32(&self, tag: &String) -> i32 {
         *self.ints.get(tag).unwrap()
     }
-}
\ No newline at end of file
+}
+
+/// Reads a string prefixed with its length in bytes.
+fn read_string(buf: &mut ByteBuffer) -> io::Result<String> {
+    check_remaining(buf, 1)?;
+    let len = buf.read_u8() as usize;
+    check_remaining(buf, len)?;
+    String::from_utf8(buf.read_bytes(len)).map_err(|err| invalid_data(err.to_string()))
+}
@@ -1,9 +1,10 @@
 use game::Block;
-use glium::uniforms::MagnifySamplerFilter;
 use glium::Frame;
 use glium::Surface;
 use graphics::*;
 
+/// The sky around the camera, with its colors, sun, moon and stars drawn by the skybox shader
+/// from `GraphicsParams::sky`.
 pub struct Skybox;
 
 impl GameObject for Skybox {
@@ -16,12 +17,8 @@ impl GameObject for Skybox {
         let vertex_buffer = &Block::get_vertex_buffer(&mut graphics.display);
         let index_buffer = &Block::get_index_buffer(&mut graphics.display);
 
-        let frame = graphics.get_frame();
+        let mut frame = graphics.get_frame();
 
-        let cubemap = graphics
-            .get_image("skybox")
-            .sampled()
-            .magnify_filter(MagnifySamplerFilter::Nearest);
         let draw_params = graphics.get_draw_params("skybox");
         let shader = graphics.get_shader("skybox");
 
@@ -33,7 +30,10 @@ impl GameObject for Skybox {
                 &uniform! {
                     view_matrix: skybox_view_matrix,
                     projection_matrix: params.projection_matrix,
-                    cubemap: cubemap
+                    zenith_color: params.sky.zenith_color,
+                    horizon_color: params.sky.horizon_color,
+                    sun_direction: params.sky.sun_direction,
+                    star_brightness: params.sky.star_brightness
                 },
                 draw_params,
             )
@@ -0,0 +1,222 @@
+use chunk::{ChunkSection, SECTION_COUNT, SECTION_SIZE};
+use game::Chunk;
+use mesher::{BlockAppearance, FACE_NORMALS};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// the face on the other side of each face, in the order of `FACE_NORMALS`
+const OPPOSITE_FACES: [usize; 6] = [2, 3, 0, 1, 5, 4];
+
+/// Which faces of a section can be seen from which other faces, i.e. are connected by a path
+/// through blocks that aren't opaque.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SectionConnectivity {
+    // bit `from * 6 + to` is set if the faces are connected
+    connections: u64,
+}
+
+impl SectionConnectivity {
+    /// A section with every face connected to every other, like one that is all air.
+    pub fn all() -> SectionConnectivity {
+        SectionConnectivity {
+            connections: (1 << 36) - 1,
+        }
+    }
+
+    pub fn none() -> SectionConnectivity {
+        SectionConnectivity { connections: 0 }
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.connections |= 1 << (from * 6 + to);
+        self.connections |= 1 << (to * 6 + from);
+    }
+
+    pub fn is_connected(&self, from: usize, to: usize) -> bool {
+        self.connections & (1 << (from * 6 + to)) != 0
+    }
+}
+
+/// Works out the connectivity of every section of a chunk, from the bottom up. Each group of
+/// connected blocks that aren't opaque connects all of the section's faces it touches.
+pub fn get_chunk_connectivity(chunk: &Chunk, appearance: &BlockAppearance) -> Vec<SectionConnectivity> {
+    (0..SECTION_COUNT)
+        .map(|section| {
+            match chunk.get_section(section) {
+                Some(section) => get_section_connectivity(section, |block| appearance.is_opaque(block)),
+                None => SectionConnectivity::all(),
+            }
+        })
+        .collect()
+}
+
+fn get_section_connectivity<F>(section: &ChunkSection, is_opaque: F) -> SectionConnectivity
+where
+    F: Fn(u16) -> bool,
+{
+    let size = SECTION_SIZE as i32;
+    let index = |x: i32, y: i32, z: i32| ((y * size + z) * size + x) as usize;
+
+    let mut open = vec![false; SECTION_SIZE * SECTION_SIZE * SECTION_SIZE];
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                let block = section.get(x as usize, y as usize, z as usize);
+                open[index(x, y, z)] = !is_opaque(block.id);
+            }
+        }
+    }
+
+    let mut connectivity = SectionConnectivity::none();
+    let mut visited = vec![false; open.len()];
+    let mut stack = Vec::new();
+
+    for start in 0..open.len() {
+        if !open[start] || visited[start] {
+            continue;
+        }
+
+        // flood fill the group of open blocks, noting which faces of the section it reaches
+        let mut faces = [false; 6];
+        visited[start] = true;
+        stack.push((
+            (start % SECTION_SIZE) as i32,
+            (start / (SECTION_SIZE * SECTION_SIZE)) as i32,
+            (start / SECTION_SIZE % SECTION_SIZE) as i32,
+        ));
+
+        while let Some((x, y, z)) = stack.pop() {
+            for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                let (nx, ny, nz) = (x + normal[0], y + normal[1], z + normal[2]);
+                if nx < 0 || nx >= size || ny < 0 || ny >= size || nz < 0 || nz >= size {
+                    faces[face] = true;
+                    continue;
+                }
+
+                let neighbor = index(nx, ny, nz);
+                if open[neighbor] && !visited[neighbor] {
+                    visited[neighbor] = true;
+                    stack.push((nx, ny, nz));
+                }
+            }
+        }
+
+        for from in 0..6 {
+            for to in 0..6 {
+                if faces[from] && faces[to] {
+                    connectivity.connect(from, to);
+                }
+            }
+        }
+    }
+
+    connectivity
+}
+
+/// Finds the sections that can be seen from the section the camera is in, as a bit mask of
+/// sections for every chunk. Starting at the camera, the search steps from section to section
+/// through their faces, but only from a face to another face that it is connected to, and never
+/// back in a direction it already went in. A section is only entered if `is_in_view` says so,
+/// e.g. because it is inside of the frustum.
+///
+/// `get_connectivity` returns `None` for sections that aren't loaded, which are never entered.
+pub fn find_visible_sections<C, V>(
+    camera_section: (i32, i32, i32),
+    get_connectivity: C,
+    is_in_view: V,
+) -> HashMap<(i32, i32), u16>
+where
+    C: Fn(i32, i32, usize) -> Option<SectionConnectivity>,
+    V: Fn(i32, i32, usize) -> bool,
+{
+    let (chunk_x, section_y, chunk_z) = camera_section;
+    // a camera above or below the world looks in through the top or bottom section
+    let section_y = section_y.max(0).min(SECTION_COUNT as i32 - 1);
+
+    let mut visible: HashMap<(i32, i32), u16> = HashMap::new();
+    let mut visited = HashSet::new();
+    // the sections to look through, with the face they were entered through and a bit for every
+    // direction taken to get there
+    let mut queue: VecDeque<((i32, i32, i32), Option<usize>, u8)> = VecDeque::new();
+
+    visited.insert((chunk_x, section_y, chunk_z));
+    queue.push_back(((chunk_x, section_y, chunk_z), None, 0));
+
+    while let Some(((x, y, z), entered_through, directions)) = queue.pop_front() {
+        *visible.entry((x, z)).or_insert(0) |= 1 << y;
+
+        // the camera's own section may not have been meshed yet, but it can still be looked out of
+        let connectivity = get_connectivity(x, z, y as usize).unwrap_or_else(SectionConnectivity::all);
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            if directions & (1 << OPPOSITE_FACES[face]) != 0 {
+                continue;
+            }
+
+            if let Some(entered_through) = entered_through {
+                if !connectivity.is_connected(entered_through, face) {
+                    continue;
+                }
+            }
+
+            let next = (x + normal[0], y + normal[1], z + normal[2]);
+            if next.1 < 0 || next.1 >= SECTION_COUNT as i32 || visited.contains(&next) {
+                continue;
+            }
+
+            if get_connectivity(next.0, next.2, next.1 as usize).is_none()
+                || !is_in_view(next.0, next.2, next.1 as usize)
+            {
+                continue;
+            }
+
+            visited.insert(next);
+            queue.push_back((next, Some(OPPOSITE_FACES[face]), directions | 1 << face));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk::BlockData;
+
+    const AIR: BlockData = BlockData { id: 0, state: 0 };
+    const STONE: BlockData = BlockData { id: 1, state: 0 };
+
+    fn get_connectivity(section: &ChunkSection) -> SectionConnectivity {
+        get_section_connectivity(section, |block| block != AIR.id)
+    }
+
+    #[test]
+    fn test_solid_section() {
+        assert_eq!(get_connectivity(&ChunkSection::new(STONE)), SectionConnectivity::none());
+    }
+
+    #[test]
+    fn test_air_section() {
+        assert_eq!(get_connectivity(&ChunkSection::new(AIR)), SectionConnectivity::all());
+    }
+
+    #[test]
+    fn test_l_shaped_tunnel() {
+        // in through the left face along x, then a turn up and out through the top face
+        let mut section = ChunkSection::new(STONE);
+        for x in 0..8 {
+            section.set(x, 8, 8, AIR);
+        }
+        for y in 8..SECTION_SIZE {
+            section.set(8, y, 8, AIR);
+        }
+
+        let (top, left) = (1, 5);
+        let connectivity = get_connectivity(&section);
+        for from in 0..6 {
+            for to in 0..6 {
+                let expected = (from == top || from == left) && (to == top || to == left);
+                assert_eq!(connectivity.is_connected(from, to), expected, "faces {} and {}", from, to);
+            }
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use chunk::SECTION_COUNT;
+use chunk_manager::get_center_chunk;
+use frustum::Frustum;
+use game::{RenderLayer, World};
+use glium::index::PrimitiveType;
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::{IndexBuffer, Surface, VertexBuffer};
+use graphics::*;
+use mesher::*;
+use nalgebra::{Matrix4, Vector3};
+use occlusion::{find_visible_sections, SectionConnectivity};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+use workers::{Job, WorkerPool};
+
+// pixels of cutout blocks with less alpha than this are left out
+const CUTOUT_ALPHA_THRESHOLD: f32 = 0.5;
+// the fractions of the render distance at which each coarser level of detail starts
+const LOD_DISTANCE_FRACTIONS: [f32; 3] = [0.5, 0.7, 0.9];
+
+/// How a chunk is meshed: its level of detail and which of its sides, in the order of
+/// `NEIGHBOR_CHUNK_OFFSETS`, border a chunk at another level. Those sides are meshed as if the
+/// neighbour was air, so that the walls along them cover up the cracks between the two meshes.
+#[derive(Copy, Clone, PartialEq)]
+struct ChunkDetail {
+    level: u8,
+    walls: [bool; 4],
+}
+
+struct LayerBuffers {
+    vertex_buffer: VertexBuffer<BlockVertex>,
+    index_buffer: IndexBuffer<u32>,
+    section_starts: Vec<usize>,
+}
+
+impl LayerBuffers {
+    /// The ranges of indices that draw the sections whose bits are set in `sections`, with
+    /// sections that follow each other merged into one range.
+    fn get_section_ranges(&self, sections: u16) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for (section, &start) in self.section_starts.iter().enumerate() {
+            if sections & (1 << section) == 0 {
+                continue;
+            }
+
+            let end = match self.section_starts.get(section + 1) {
+                Some(&end) => end,
+                None => self.index_buffer.len(),
+            };
+            if start == end {
+                continue;
+            }
+
+            if let Some(last) = ranges.last_mut() {
+                if last.end == start {
+                    last.end = end;
+                    continue;
+                }
+            }
+            ranges.push(start..end);
+        }
+
+        ranges
+    }
+}
+
+struct ChunkBuffers {
+    opaque: Option<LayerBuffers>,
+    cutout: Option<LayerBuffers>,
+    translucent: Option<LayerBuffers>,
+    /// The translucent mesh is kept around to sort its quads again whenever the camera moves to
+    /// another block.
+    translucent_mesh: ChunkMesh,
+    sorted_from: Option<[i32; 3]>,
+    /// The box around the chunk's meshes, which is tested against the camera's frustum.
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+/// Draws the world one chunk mesh at a time: first the opaque and cutout layers of every chunk,
+/// then the translucent layers from the furthest chunk to the nearest. Chunks outside of the
+/// camera's frustum are skipped, and so are the sections of a chunk that can't be seen from the
+/// camera's section through the open faces of the sections in between. Chunks further away are
+/// meshed at coarser levels of detail, in rings around the camera.
+pub struct WorldRenderer {
+    pub meshing_mode: MeshingMode,
+    /// The distances in chunks from the camera at which each coarser level of detail starts, from
+    /// the nearest up. Chunks closer than the first one are meshed with every block.
+    pub lod_distances: Vec<i32>,
+    chunk_buffers: HashMap<(i32, i32), ChunkBuffers>,
+    /// The detail every chunk was last sent off to be meshed at.
+    details: HashMap<(i32, i32), ChunkDetail>,
+    /// The connectivity of every loaded chunk's sections, including chunks with empty meshes.
+    /// Chunks that haven't been meshed yet have every face connected.
+    connectivity: HashMap<(i32, i32), Vec<SectionConnectivity>>,
+    meshing: HashSet<(i32, i32)>,
+    appearance: Arc<BlockAppearance>,
+    // the chunks with translucent faces, furthest from the camera first
+    translucent_order: Vec<(i32, i32)>,
+    // the chunk and section the camera is in, as of the last update
+    camera_section: (i32, i32, i32),
+}
+
+impl WorldRenderer {
+    /// Chunks further than half of `render_distance` get coarser, and the coarsest level starts
+    /// before its edge, so that every level is in use.
+    pub fn new(appearance: BlockAppearance, render_distance: u8) -> WorldRenderer {
+        WorldRenderer {
+            meshing_mode: MeshingMode::Greedy,
+            lod_distances: LOD_DISTANCE_FRACTIONS
+                .iter()
+                .map(|fraction| i32::max(1, (render_distance as f32 * fraction).round() as i32))
+                .collect(),
+            chunk_buffers: HashMap::new(),
+            details: HashMap::new(),
+            connectivity: HashMap::new(),
+            meshing: HashSet::new(),
+            appearance: Arc::new(appearance),
+            translucent_order: Vec::new(),
+            camera_section: (0, 0, 0),
+        }
+    }
+
+    /// Drops the buffers of chunks that were unloaded and sends the chunks that changed since the
+    /// last update off to be meshed, along with the chunks whose detail changed as the camera
+    /// moved. Everything else keeps its cached buffers, except that translucent faces are sorted
+    /// again when the camera moved.
+    pub fn update(&mut self, world: &mut World, workers: &WorkerPool, camera: &Vector3<f32>) {
+        self.chunk_buffers
+            .retain(|&(chunk_x, chunk_z), _| world.is_chunk_loaded(chunk_x, chunk_z));
+        self.connectivity
+            .retain(|&(chunk_x, chunk_z), _| world.is_chunk_loaded(chunk_x, chunk_z));
+        self.details
+            .retain(|&(chunk_x, chunk_z), _| world.is_chunk_loaded(chunk_x, chunk_z));
+
+        // until its mesh arrives a chunk can't hide anything, or whatever is behind it would
+        // flicker out whenever a chunk is loaded between it and the camera
+        for position in world.chunks.keys() {
+            self.connectivity
+                .entry(*position)
+                .or_insert_with(|| vec![SectionConnectivity::all(); SECTION_COUNT]);
+        }
+
+        // a chunk that is still being meshed stays dirty until its mesh comes back, so that a newer
+        // mesh can never be overwritten by an older one
+        let center = get_center_chunk(camera);
+        let stale_chunks: Vec<((i32, i32), ChunkDetail)> = world
+            .chunks
+            .iter()
+            .filter(|&(position, _)| !self.meshing.contains(position))
+            .map(|(position, chunk)| (*position, chunk, self.get_chunk_detail(*position, center)))
+            .filter(|&(position, chunk, detail)| chunk.dirty || self.details.get(&position) != Some(&detail))
+            .map(|(position, _, detail)| (position, detail))
+            .collect();
+
+        for ((chunk_x, chunk_z), detail) in stale_chunks {
+            // marked clean before the job gets to share the chunk, which would otherwise make the
+            // world copy it. Edits made after this point copy it and mark it dirty again. Chunks
+            // that are only meshed at a new detail are left alone, they aren't copied for nothing.
+            if world.chunks[&(chunk_x, chunk_z)].dirty {
+                world.get_chunk_mut(chunk_x, chunk_z).unwrap().dirty = false;
+            }
+
+            workers.submit(Job::Mesh {
+                chunk_x: chunk_x,
+                chunk_z: chunk_z,
+                center: world.chunks[&(chunk_x, chunk_z)].clone(),
+                neighbors: NEIGHBOR_CHUNK_OFFSETS
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &(dx, dz))| {
+                        if index < 4 && detail.walls[index] {
+                            None
+                        } else {
+                            world.chunks.get(&(chunk_x + dx, chunk_z + dz)).cloned()
+                        }
+                    })
+                    .collect(),
+                appearance: self.appearance.clone(),
+                mode: self.meshing_mode,
+                level: detail.level,
+            });
+            self.meshing.insert((chunk_x, chunk_z));
+            self.details.insert((chunk_x, chunk_z), detail);
+        }
+
+        let camera_block = [
+            f32::floor(camera.x + 0.5) as i32,
+            f32::floor(camera.y + 0.5) as i32,
+            f32::floor(camera.z + 0.5) as i32,
+        ];
+        self.camera_section = (camera_block[0] >> 4, camera_block[1] >> 4, camera_block[2] >> 4);
+
+        self.sort_translucent_faces(camera);
+    }
+
+    /// Uploads the meshes built by the worker pool, unless their chunk was unloaded in the meantime.
+    pub fn receive_meshes(
+        &mut self,
+        graphics: &mut Graphics,
+        world: &World,
+        chunk_x: i32,
+        chunk_z: i32,
+        meshes: ChunkMeshes,
+        connectivity: Vec<SectionConnectivity>,
+    ) {
+        self.meshing.remove(&(chunk_x, chunk_z));
+
+        if world.is_chunk_loaded(chunk_x, chunk_z) {
+            self.upload(graphics, chunk_x, chunk_z, meshes);
+            self.connectivity.insert((chunk_x, chunk_z), connectivity);
+        }
+    }
+
+    fn upload(&mut self, graphics: &mut Graphics, chunk_x: i32, chunk_z: i32, meshes: ChunkMeshes) {
+        let (min, max) = match meshes.get_bounds() {
+            Some(bounds) => bounds,
+            None => {
+                self.chunk_buffers.remove(&(chunk_x, chunk_z));
+                return;
+            }
+        };
+
+        let translucent = create_layer_buffers(graphics, meshes.get(RenderLayer::Translucent), true);
+        let opaque = create_layer_buffers(graphics, meshes.get(RenderLayer::Opaque), false);
+        let cutout = create_layer_buffers(graphics, meshes.get(RenderLayer::Cutout), false);
+
+        self.chunk_buffers.insert(
+            (chunk_x, chunk_z),
+            ChunkBuffers {
+                opaque: opaque,
+                cutout: cutout,
+                translucent: translucent,
+                translucent_mesh: meshes.into_translucent(),
+                sorted_from: None,
+                min: Vector3::new(min[0], min[1], min[2]),
+                max: Vector3::new(max[0], max[1], max[2]),
+            },
+        );
+    }
+
+    /// The level of detail of a chunk is the number of `lod_distances` it is at least as far
+    /// from the chunk the camera is in.
+    fn get_detail_level(&self, chunk: (i32, i32), center: (i32, i32)) -> u8 {
+        let (dx, dz) = (chunk.0 - center.0, chunk.1 - center.1);
+        let distance_squared = dx * dx + dz * dz;
+
+        self.lod_distances
+            .iter()
+            .take(MAX_DETAIL_LEVEL as usize)
+            .filter(|&&distance| distance_squared >= distance * distance)
+            .count() as u8
+    }
+
+    fn get_chunk_detail(&self, chunk: (i32, i32), center: (i32, i32)) -> ChunkDetail {
+        let level = self.get_detail_level(chunk, center);
+        let mut walls = [false; 4];
+        for (wall, &(dx, dz)) in walls.iter_mut().zip(NEIGHBOR_CHUNK_OFFSETS.iter()) {
+            *wall = self.get_detail_level((chunk.0 + dx, chunk.1 + dz), center) != level;
+        }
+
+        ChunkDetail {
+            level: level,
+            walls: walls,
+        }
+    }
+
+    fn sort_translucent_faces(&mut self, camera: &Vector3<f32>) {
+        let eye = [camera.x, camera.y, camera.z];
+        let camera_block = [
+            f32::floor(camera.x + 0.5) as i32,
+            f32::floor(camera.y + 0.5) as i32,
+            f32::floor(camera.z + 0.5) as i32,
+        ];
+
+        for buffers in self.chunk_buffers.values_mut() {
+            if buffers.sorted_from == Some(camera_block) {
+                continue;
+            }
+
+            if let Some(ref translucent) = buffers.translucent {
+                let indices = buffers.translucent_mesh.get_sorted_indices(eye);
+                translucent.index_buffer.write(&indices);
+            }
+            buffers.sorted_from = Some(camera_block);
+        }
+
+        let mut order: Vec<((i32, i32), f32)> = self
+            .chunk_buffers
+            .iter()
+            .filter(|&(_, buffers)| buffers.translucent.is_some())
+            .map(|(&(chunk_x, chunk_z), _)| {
+                let dx = (chunk_x * 16) as f32 + 7.5 - camera.x;
+                let dz = (chunk_z * 16) as f32 + 7.5 - camera.z;
+                ((chunk_x, chunk_z), dx * dx + dz * dz)
+            })
+            .collect();
+        order.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+        self.translucent_order = order.into_iter().map(|(chunk, _)| chunk).collect();
+    }
+}
+
+fn create_layer_buffers(graphics: &Graphics, mesh: &ChunkMesh, dynamic: bool) -> Option<LayerBuffers> {
+    if mesh.is_empty() {
+        return None;
+    }
+
+    let index_buffer = if dynamic {
+        IndexBuffer::dynamic(&graphics.display, PrimitiveType::TrianglesList, &mesh.indices)
+    } else {
+        IndexBuffer::new(&graphics.display, PrimitiveType::TrianglesList, &mesh.indices)
+    };
+
+    Some(LayerBuffers {
+        vertex_buffer: VertexBuffer::new(&graphics.display, &mesh.vertices).unwrap(),
+        index_buffer: index_buffer.unwrap(),
+        section_starts: mesh.section_starts.clone(),
+    })
+}
+
+impl GameObject for WorldRenderer {
+    fn draw(&self, graphics: &mut Graphics, params: &GraphicsParams) {
+        let mut frame = graphics.get_frame();
+
+        let sampler = graphics
+            .get_image("atlas")
+            .sampled()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .minify_filter(MinifySamplerFilter::NearestMipmapLinear);
+        let shader = graphics.get_shader("block");
+        let fog_color = graphics.config.get_fog_color(&params.sky);
+
+        let frustum = Frustum::from_matrix(
+            &(Matrix4::from(params.projection_matrix) * Matrix4::from(params.view_matrix)),
+        );
+        let visible_sections = find_visible_sections(
+            self.camera_section,
+            |chunk_x, chunk_z, section| {
+                self.connectivity
+                    .get(&(chunk_x, chunk_z))
+                    .map(|connectivity| connectivity[section])
+            },
+            |chunk_x, chunk_z, section| {
+                let min = Vector3::new(
+                    (chunk_x * 16) as f32 - 0.5,
+                    (section * 16) as f32 - 0.5,
+                    (chunk_z * 16) as f32 - 0.5,
+                );
+                frustum.intersects_box(&min, &(min + Vector3::new(16.0, 16.0, 16.0)))
+            },
+        );
+
+        // each chunk that is drawn at all along with the bits of its visible sections
+        let visible: Vec<(&ChunkBuffers, u16)> = self
+            .chunk_buffers
+            .iter()
+            .filter(|&(_, buffers)| frustum.intersects_box(&buffers.min, &buffers.max))
+            .filter_map(|(chunk, buffers)| visible_sections.get(chunk).map(|&sections| (buffers, sections)))
+            .collect();
+
+        // translucent quads are sorted across the whole chunk, so their sections can't be told
+        // apart anymore and the whole layer is drawn if any section is visible
+        let passes: Vec<(Vec<(&LayerBuffers, u16)>, &str, f32)> = vec![
+            (
+                visible
+                    .iter()
+                    .filter_map(|&(buffers, sections)| buffers.opaque.as_ref().map(|opaque| (opaque, sections)))
+                    .collect(),
+                "block",
+                0.0,
+            ),
+            (
+                visible
+                    .iter()
+                    .filter_map(|&(buffers, sections)| buffers.cutout.as_ref().map(|cutout| (cutout, sections)))
+                    .collect(),
+                "block",
+                CUTOUT_ALPHA_THRESHOLD,
+            ),
+            (
+                self.translucent_order
+                    .iter()
+                    .filter(|chunk| visible_sections.contains_key(chunk))
+                    .filter_map(|chunk| self.chunk_buffers.get(chunk))
+                    .filter(|buffers| frustum.intersects_box(&buffers.min, &buffers.max))
+                    .filter_map(|buffers| buffers.translucent.as_ref())
+                    .map(|translucent| (translucent, u16::max_value()))
+                    .collect(),
+                "block_translucent",
+                0.0,
+            ),
+        ];
+
+        for (layer_buffers, draw_params, alpha_threshold) in passes {
+            let draw_params = graphics.get_draw_params(draw_params);
+
+            for (buffers, sections) in layer_buffers {
+                for range in buffers.get_section_ranges(sections) {
+                    frame
+                        .draw(
+                            &buffers.vertex_buffer,
+                            buffers.index_buffer.slice(range).unwrap(),
+                            shader,
+                            &uniform! {
+                                sampler: sampler,
+                                view_matrix: params.view_matrix,
+                                projection_matrix: params.projection_matrix,
+                                alpha_threshold: alpha_threshold,
+                                daylight: params.sky.daylight,
+                                fog_start: graphics.config.fog_start,
+                                fog_end: graphics.config.fog_end,
+                                fog_color: fog_color
+                            },
+                            draw_params,
+                        )
+                        .unwrap();
+                }
+            }
+        }
+    }
+}
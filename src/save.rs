@@ -0,0 +1,189 @@
+use bytebuffer::ByteBuffer;
+use chunk::check_remaining;
+use game::Chunk;
+use nbt::Nbt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Bumped whenever the chunk format changes, so that old saves fail to load instead of loading garbage.
+const CHUNK_FORMAT_VERSION: u8 = 2;
+
+/// What `level.dat` stores about the world, everything but its chunks.
+#[derive(Copy, Clone, Debug)]
+pub struct Level {
+    pub seed: u64,
+    /// The ticks of the world's `WorldTime`.
+    pub time: u64,
+    /// The tick the world's `TickScheduler` is at, which is not the same as `time` once the time
+    /// was set or frozen.
+    pub tick: u64,
+}
+
+impl Level {
+    /// A new world, starting at sunrise.
+    pub fn new(seed: u64) -> Level {
+        Level {
+            seed: seed,
+            time: 0,
+            tick: 0,
+        }
+    }
+}
+
+/// A world saved on disk as a `level.dat` with the world's settings and one file per chunk.
+#[derive(Clone)]
+pub struct WorldSave {
+    directory: PathBuf,
+}
+
+impl WorldSave {
+    /// Opens the save in the given directory, creating it if it doesn't exist yet.
+    pub fn open(directory: &str) -> io::Result<WorldSave> {
+        let save = WorldSave {
+            directory: PathBuf::from(directory),
+        };
+        fs::create_dir_all(save.directory.join("chunks"))?;
+
+        Ok(save)
+    }
+
+    /// The world's `level.dat`, or `None` for a new world. Values missing from saves written
+    /// before they were added start at 0.
+    pub fn load_level(&self) -> io::Result<Option<Level>> {
+        let path = self.directory.join("level.dat");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let nbt = Nbt::from_buffer(ByteBuffer::from_bytes(&fs::read(path)?))?;
+        let seed = read_number(&nbt, "seed")?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "level.dat has no seed")
+        })?;
+
+        Ok(Some(Level {
+            seed: seed,
+            time: read_number(&nbt, "time")?.unwrap_or(0),
+            tick: read_number(&nbt, "tick")?.unwrap_or(0),
+        }))
+    }
+
+    pub fn save_level(&self, level: &Level) -> io::Result<()> {
+        // numbers are stored as strings, since NBT ints can't hold a u64
+        let mut nbt = Nbt::new();
+        nbt.set_string("seed", &level.seed.to_string());
+        nbt.set_string("time", &level.time.to_string());
+        nbt.set_string("tick", &level.tick.to_string());
+
+        let mut buf = ByteBuffer::new();
+        nbt.to_buffer(&mut buf);
+        fs::write(self.directory.join("level.dat"), buf.to_bytes())
+    }
+
+    /// Loads a chunk that was saved before, or returns `None` if it never was. A chunk with blocks
+    /// for which `is_defined` is false fails to load.
+    pub fn load_chunk(&self, chunk_x: i32, chunk_z: i32, is_defined: &Fn(u16) -> bool) -> io::Result<Option<Chunk>> {
+        let path = self.get_chunk_path(chunk_x, chunk_z);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut buf = ByteBuffer::from_bytes(&fs::read(path)?);
+        check_remaining(&buf, 1)?;
+        let version = buf.read_u8();
+        if version != CHUNK_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {}, {} has unknown format version {}", chunk_x, chunk_z, version),
+            ));
+        }
+
+        Ok(Some(Chunk::from_buffer(&mut buf, is_defined)?))
+    }
+
+    pub fn save_chunk(&self, chunk_x: i32, chunk_z: i32, chunk: &Chunk) -> io::Result<()> {
+        let mut buf = ByteBuffer::new();
+        buf.write_u8(CHUNK_FORMAT_VERSION);
+        chunk.to_buffer(&mut buf);
+
+        fs::write(self.get_chunk_path(chunk_x, chunk_z), buf.to_bytes())
+    }
+
+    fn get_chunk_path(&self, chunk_x: i32, chunk_z: i32) -> PathBuf {
+        self.directory
+            .join("chunks")
+            .join(format!("{}.{}.chunk", chunk_x, chunk_z))
+    }
+}
+
+fn read_number(nbt: &Nbt, tag: &str) -> io::Result<Option<u64>> {
+    match nbt.find_string(tag) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("level.dat has an invalid {}", tag))
+        }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn open_temporary_save(name: &str) -> WorldSave {
+        let directory = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+        WorldSave::open(directory.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_level_round_trip() {
+        let save = open_temporary_save("crate-test-level-round-trip");
+        assert!(save.load_level().unwrap().is_none());
+
+        let level = Level {
+            seed: u64::max_value(),
+            time: 123456,
+            tick: 654321,
+        };
+        save.save_level(&level).unwrap();
+        let loaded = save.load_level().unwrap().unwrap();
+
+        assert_eq!(loaded.seed, level.seed);
+        assert_eq!(loaded.time, level.time);
+        assert_eq!(loaded.tick, level.tick);
+    }
+
+    #[test]
+    fn test_level_without_time() {
+        let save = open_temporary_save("crate-test-level-without-time");
+        let mut nbt = Nbt::new();
+        nbt.set_string("seed", "42");
+        let mut buf = ByteBuffer::new();
+        nbt.to_buffer(&mut buf);
+        fs::write(save.directory.join("level.dat"), buf.to_bytes()).unwrap();
+
+        let loaded = save.load_level().unwrap().unwrap();
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.time, 0);
+        assert_eq!(loaded.tick, 0);
+    }
+
+    #[test]
+    fn test_truncated_level() {
+        let save = open_temporary_save("crate-test-truncated-level");
+        save.save_level(&Level {
+            seed: 42,
+            time: 0,
+            tick: 0,
+        })
+        .unwrap();
+        let bytes = fs::read(save.directory.join("level.dat")).unwrap();
+
+        for len in 0..bytes.len() {
+            fs::write(save.directory.join("level.dat"), &bytes[..len]).unwrap();
+            let err = save.load_level().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use chunk::SECTION_SIZE;
+use std::collections::{BTreeMap, HashSet};
+use worldgen::{hash_seed, SeededRandom};
+
+/// How many blocks of every section get a random tick each game tick.
+pub const DEFAULT_RANDOM_TICK_SPEED: u32 = 3;
+
+// keeps the random ticks' seeds apart from the seeds of world generation
+const RANDOM_TICK_SALT: u64 = 0x7469_636B;
+
+/// A block update that was asked for some ticks in advance.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScheduledTick {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// The block that asked for the update. If the block was replaced in the meantime, the update
+    /// is dropped.
+    pub block: u16,
+}
+
+/// Decides when blocks update on their own. Blocks can ask for an update a number of ticks in
+/// advance, e.g. fluids that flow on after a delay, and every section of a loaded chunk gets a few
+/// random ticks each game tick, which slow processes like grass spreading are built on.
+///
+/// Both play out the same way every time for the same seed: scheduled ticks that are due at the
+/// same tick run in the order they were scheduled in, and the blocks that get random ticks only
+/// depend on the seed, the tick and the section. The scheduler counts ticks on its own, so that
+/// freezing or setting the time of day doesn't hold updates back or skip them.
+pub struct TickScheduler {
+    /// How many blocks of every section get a random tick each game tick, 0 to turn them off.
+    pub random_tick_speed: u32,
+    seed: u64,
+    current_tick: u64,
+    /// The updates by the tick they are due at, each tick's in the order they were scheduled in.
+    scheduled: BTreeMap<u64, Vec<ScheduledTick>>,
+    // the blocks with an update pending, which aren't scheduled a second time
+    pending: HashSet<(i32, i32, i32, u16)>,
+}
+
+impl TickScheduler {
+    pub fn new(seed: u64) -> TickScheduler {
+        TickScheduler {
+            random_tick_speed: DEFAULT_RANDOM_TICK_SPEED,
+            seed: hash_seed(seed, RANDOM_TICK_SALT),
+            current_tick: 0,
+            scheduled: BTreeMap::new(),
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Changes the seed the random ticks are picked with, usually to the world's seed once it is
+    /// known.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = hash_seed(seed, RANDOM_TICK_SALT);
+    }
+
+    /// The number of ticks run so far.
+    pub fn get_current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    /// Carries on counting from the tick a saved world stopped at, so that its random ticks don't
+    /// repeat the ones it already had. Updates are scheduled at absolute ticks, so this has to be
+    /// called before any are.
+    pub fn set_current_tick(&mut self, tick: u64) {
+        self.current_tick = tick;
+    }
+
+    /// Schedules an update of a block `delay` ticks from now, at least one. Does nothing if the
+    /// block already has an update pending.
+    pub fn schedule(&mut self, x: i32, y: i32, z: i32, block: u16, delay: u64) {
+        if !self.pending.insert((x, y, z, block)) {
+            return;
+        }
+
+        self.scheduled
+            .entry(self.current_tick + delay.max(1))
+            .or_insert_with(Vec::new)
+            .push(ScheduledTick {
+                x: x,
+                y: y,
+                z: z,
+                block: block,
+            });
+    }
+
+    /// Moves on to the next tick and returns the updates that are due at it, in the order they
+    /// were scheduled in.
+    pub fn advance(&mut self) -> Vec<ScheduledTick> {
+        self.current_tick += 1;
+
+        let due = match self.scheduled.remove(&self.current_tick) {
+            Some(due) => due,
+            None => return Vec::new(),
+        };
+        for tick in &due {
+            self.pending.remove(&(tick.x, tick.y, tick.z, tick.block));
+        }
+
+        due
+    }
+
+    /// The random number generator for a section's random ticks at the current tick, which picks
+    /// the blocks that get them and is handed on to those blocks' updates.
+    pub fn get_section_random(&self, chunk_x: i32, chunk_z: i32, section: usize) -> SeededRandom {
+        let seed = hash_seed(hash_seed(self.seed, self.current_tick), chunk_x as u64);
+        SeededRandom::new(hash_seed(hash_seed(seed, chunk_z as u64), section as u64))
+    }
+}
+
+/// Picks the position of the next block of a section to get a random tick.
+pub fn pick_random_block(random: &mut SeededRandom, chunk_x: i32, chunk_z: i32, section: usize) -> (i32, i32, i32) {
+    let x = chunk_x * 16 + random.next_int(16);
+    let y = (section * SECTION_SIZE) as i32 + random.next_int(16);
+    let z = chunk_z * 16 + random.next_int(16);
+
+    (x, y, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The blocks that get random ticks in a few sections over the next ticks.
+    fn pick_random_blocks(scheduler: &mut TickScheduler) -> Vec<(i32, i32, i32)> {
+        let mut blocks = Vec::new();
+        for _ in 0..10 {
+            scheduler.advance();
+            for &(chunk_x, chunk_z, section) in [(0, 0, 0), (0, 0, 4), (-3, 7, 4), (12, -5, 15)].iter() {
+                let mut random = scheduler.get_section_random(chunk_x, chunk_z, section);
+                for _ in 0..DEFAULT_RANDOM_TICK_SPEED {
+                    blocks.push(pick_random_block(&mut random, chunk_x, chunk_z, section));
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_same_seed_same_random_ticks() {
+        let blocks = pick_random_blocks(&mut TickScheduler::new(42));
+        assert_eq!(blocks, pick_random_blocks(&mut TickScheduler::new(42)));
+        assert_ne!(blocks, pick_random_blocks(&mut TickScheduler::new(43)));
+    }
+
+    #[test]
+    fn test_restored_tick_continues_random_ticks() {
+        let mut scheduler = TickScheduler::new(42);
+        pick_random_blocks(&mut scheduler);
+        let mut restored = TickScheduler::new(42);
+        restored.set_current_tick(scheduler.get_current_tick());
+
+        assert_eq!(pick_random_blocks(&mut scheduler), pick_random_blocks(&mut restored));
+    }
+
+    #[test]
+    fn test_scheduled_ticks_in_order() {
+        let mut scheduler = TickScheduler::new(0);
+        scheduler.schedule(1, 2, 3, 14, 2);
+        scheduler.schedule(4, 5, 6, 15, 1);
+        scheduler.schedule(7, 8, 9, 14, 2);
+        // already pending, so it isn't scheduled again
+        scheduler.schedule(1, 2, 3, 14, 1);
+
+        assert_eq!(scheduler.advance().iter().map(|tick| tick.x).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(scheduler.advance().iter().map(|tick| tick.x).collect::<Vec<_>>(), vec![1, 7]);
+        assert!(scheduler.advance().is_empty());
+    }
+}
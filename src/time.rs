@@ -0,0 +1,156 @@
+use std::f32::consts::PI;
+
+/// How many times per second the world is updated, regardless of the frame rate.
+pub const TICKS_PER_SECOND: u32 = 20;
+/// The length of a day and night, 20 minutes.
+pub const TICKS_PER_DAY: u64 = 24000;
+
+// the most ticks run in one frame, so that a long hitch doesn't freeze the game while it catches up
+const MAX_TICKS_PER_FRAME: u32 = 10;
+// sky light never drops below this, so that nights aren't pitch black
+const MIN_DAYLIGHT: f32 = 0.2;
+
+const DAY_ZENITH: [f32; 3] = [0.32, 0.56, 0.96];
+const DAY_HORIZON: [f32; 3] = [0.72, 0.84, 1.0];
+const NIGHT_ZENITH: [f32; 3] = [0.01, 0.01, 0.04];
+const NIGHT_HORIZON: [f32; 3] = [0.04, 0.05, 0.11];
+const SUNSET_HORIZON: [f32; 3] = [0.98, 0.48, 0.2];
+
+/// The time of the world in ticks since it was created. Tick 0 is sunrise, noon is a quarter of a
+/// day later.
+#[derive(Copy, Clone, Debug)]
+pub struct WorldTime {
+    ticks: u64,
+    frozen: bool,
+}
+
+impl WorldTime {
+    pub fn new(ticks: u64) -> WorldTime {
+        WorldTime {
+            ticks: ticks,
+            frozen: false,
+        }
+    }
+
+    /// Advances the time by one tick, unless it is frozen.
+    pub fn tick(&mut self) {
+        if !self.frozen {
+            self.ticks += 1;
+        }
+    }
+
+    pub fn get_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn set_ticks(&mut self, ticks: u64) {
+        self.ticks = ticks;
+    }
+
+    /// How far into the current day it is, from 0 at sunrise up to 1 at the next one.
+    pub fn get_time_of_day(&self) -> f32 {
+        (self.ticks % TICKS_PER_DAY) as f32 / TICKS_PER_DAY as f32
+    }
+
+    /// Jumps to the given time of the current day, see `get_time_of_day`.
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        let day = self.ticks / TICKS_PER_DAY;
+        let ticks = (time_of_day.max(0.0).min(1.0) * TICKS_PER_DAY as f32) as u64;
+        self.ticks = day * TICKS_PER_DAY + ticks.min(TICKS_PER_DAY - 1);
+    }
+
+    /// Stops the time, e.g. to keep it noon while building.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    // not bound to anything yet, `--freeze-time` can only freeze the time
+    #[allow(dead_code)]
+    pub fn unfreeze(&mut self) {
+        self.frozen = false;
+    }
+
+    #[allow(dead_code)]
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// How the sky looks at this time. The sun rises in the east (+x), goes over the top and sets
+    /// in the west, with the moon always on the opposite side.
+    pub fn get_sky(&self) -> Sky {
+        let angle = self.get_time_of_day() * 2.0 * PI;
+        let sun_direction = [angle.cos(), angle.sin(), 0.0];
+        let sun_height = sun_direction[1];
+
+        let day = smoothstep(-0.15, 0.15, sun_height);
+        // the horizon glows orange while the sun is close to it
+        let sunset = 1.0 - smoothstep(0.0, 0.3, sun_height.abs());
+
+        let zenith_color = mix(NIGHT_ZENITH, DAY_ZENITH, day);
+        let horizon_color = mix(mix(NIGHT_HORIZON, DAY_HORIZON, day), SUNSET_HORIZON, sunset * 0.8);
+
+        Sky {
+            zenith_color: zenith_color,
+            horizon_color: horizon_color,
+            sun_direction: sun_direction,
+            star_brightness: 1.0 - smoothstep(-0.25, 0.05, sun_height),
+            daylight: MIN_DAYLIGHT + (1.0 - MIN_DAYLIGHT) * day,
+        }
+    }
+}
+
+/// The colors of the sky and where the sun is, worked out from the `WorldTime` every frame.
+#[derive(Copy, Clone, Debug)]
+pub struct Sky {
+    /// The color straight up.
+    pub zenith_color: [f32; 3],
+    /// The color at the horizon and below it.
+    pub horizon_color: [f32; 3],
+    /// Points from the camera towards the sun. The moon is in the opposite direction.
+    pub sun_direction: [f32; 3],
+    /// From 0 during the day to 1 at night.
+    pub star_brightness: f32,
+    /// How bright sky light is, from 0 to 1. The sky light of blocks is multiplied by it.
+    pub daylight: f32,
+}
+
+/// Turns the time between frames into a whole number of fixed length ticks, carrying the rest over
+/// to the next frame.
+pub struct TickClock {
+    accumulated: f32,
+}
+
+impl TickClock {
+    pub fn new() -> TickClock {
+        TickClock { accumulated: 0.0 }
+    }
+
+    /// Adds the seconds since the last frame and returns how many ticks have to be run for them.
+    pub fn advance(&mut self, seconds: f32) -> u32 {
+        let tick_length = 1.0 / TICKS_PER_SECOND as f32;
+        self.accumulated += seconds;
+
+        let ticks = (self.accumulated / tick_length) as u32;
+        self.accumulated -= ticks as f32 * tick_length;
+
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulated = 0.0;
+            return MAX_TICKS_PER_FRAME;
+        }
+
+        ticks
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
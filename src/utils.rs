@@ -21,8 +21,12 @@ pub fn file_to_bytes(file: &str) -> Vec<u8> {
     bytes
 }
 
+pub fn load_rgba_image_from_file(file: &str) -> image::RgbaImage {
+	image::load(Cursor::new(&file_to_bytes(file)), image::PNG).unwrap().to_rgba()
+}
+
 pub fn load_image_from_file<'a>(file: &str) -> glium::texture::RawImage2d<'a, u8> {
-	let image = image::load(Cursor::new(&file_to_bytes(file)), image::PNG).unwrap().to_rgba();
+	let image = load_rgba_image_from_file(file);
 	let dimensions = image.dimensions();
 
 	glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw()[..], dimensions)
@@ -0,0 +1,101 @@
+use chunk::BlockData;
+use fluid::Fluid;
+use game::{BlockType, World};
+use lighting::LightType;
+use std::collections::{HashSet, VecDeque};
+use worldgen::SeededRandom;
+
+/// Set in the state of leaves that never decay, e.g. ones placed by a player.
+pub const PERSISTENT_LEAVES: u16 = 1;
+
+// leaves further than this from a log, counted in steps through other leaves, decay
+const LEAF_DECAY_DISTANCE: usize = 6;
+// grass only spreads onto dirt with at least this much light above it
+const MIN_GRASS_SPREAD_LIGHT: u8 = 9;
+// how many blocks around a grass block it tries to spread to with each random tick
+const GRASS_SPREAD_ATTEMPTS: u32 = 4;
+
+/// The random tick of a grass block: it dies back to dirt once it is covered, otherwise it tries
+/// to spread onto lit dirt up to a block away and from three blocks below up to one above.
+pub fn tick_grass(world: &mut World, random: &mut SeededRandom, x: i32, y: i32, z: i32) {
+    if is_covered(world, x, y, z) {
+        world.set_block_data(x, y, z, BlockData::new(BlockType::Dirt as u16, 0));
+        return;
+    }
+
+    for _ in 0..GRASS_SPREAD_ATTEMPTS {
+        let (target_x, target_y, target_z) = (
+            x + random.next_int(3) - 1,
+            y + random.next_int(5) - 3,
+            z + random.next_int(3) - 1,
+        );
+
+        if world.get_block_id(target_x, target_y, target_z) != BlockType::Dirt as u16
+            || is_covered(world, target_x, target_y, target_z)
+        {
+            continue;
+        }
+
+        let light = world
+            .get_light(LightType::Sky, target_x, target_y + 1, target_z)
+            .max(world.get_light(LightType::Block, target_x, target_y + 1, target_z));
+        if light >= MIN_GRASS_SPREAD_LIGHT {
+            world.set_block_data(target_x, target_y, target_z, BlockData::new(BlockType::Grass as u16, 0));
+        }
+    }
+}
+
+/// The random tick of a leaves block: it decays unless a log can be reached from it through other
+/// leaves, or it is persistent.
+pub fn tick_leaves(world: &mut World, x: i32, y: i32, z: i32, state: u16) {
+    if state & PERSISTENT_LEAVES == 0 && !is_connected_to_log(world, x, y, z) {
+        world.set_block_data(x, y, z, BlockData::air());
+    }
+}
+
+// grass can't grow under opaque blocks or fluids
+fn is_covered(world: &World, x: i32, y: i32, z: i32) -> bool {
+    let above = world.get_block_id(x, y + 1, z);
+    world.is_opaque(above) || Fluid::from_block(above).is_some()
+}
+
+// searches the leaves around a leaves block for a log. Leaves that reach into a chunk that isn't
+// loaded count as connected, since their log may be in there.
+fn is_connected_to_log(world: &World, x: i32, y: i32, z: i32) -> bool {
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((x, y, z));
+    queue.push_back((x, y, z, 0));
+
+    while let Some((x, y, z, distance)) = queue.pop_front() {
+        let neighbors = [
+            (x + 1, y, z),
+            (x - 1, y, z),
+            (x, y + 1, z),
+            (x, y - 1, z),
+            (x, y, z + 1),
+            (x, y, z - 1),
+        ];
+
+        for &neighbor in neighbors.iter() {
+            let (neighbor_x, neighbor_y, neighbor_z) = neighbor;
+            if !world.is_loaded(neighbor_x, neighbor_y, neighbor_z) {
+                if neighbor_y >= 0 && neighbor_y <= 255 {
+                    return true;
+                }
+                continue;
+            }
+
+            let block = world.get_block_id(neighbor_x, neighbor_y, neighbor_z);
+            if block == BlockType::Log as u16 {
+                return true;
+            }
+
+            if block == BlockType::Leaves as u16 && distance + 1 < LEAF_DECAY_DISTANCE && visited.insert(neighbor) {
+                queue.push_back((neighbor_x, neighbor_y, neighbor_z, distance + 1));
+            }
+        }
+    }
+
+    false
+}
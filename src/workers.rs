@@ -0,0 +1,166 @@
+use game::Chunk;
+use lighting::{light_chunk, LightProperties};
+use mesher::*;
+use occlusion::{get_chunk_connectivity, SectionConnectivity};
+use save::WorldSave;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use worldgen::TerrainGenerator;
+
+pub const DEFAULT_WORKER_THREADS: usize = 3;
+
+pub enum Job {
+    /// Loads a chunk from the save, or generates it if it was never saved or can't be read, and
+    /// lights it.
+    Load {
+        chunk_x: i32,
+        chunk_z: i32,
+    },
+    /// Meshes a chunk and its neighbours as they were when the job was submitted. The world copies
+    /// a chunk before changing it while a job still holds it.
+    Mesh {
+        chunk_x: i32,
+        chunk_z: i32,
+        center: Arc<Chunk>,
+        /// The chunks around it, in the order of `NEIGHBOR_CHUNK_OFFSETS`.
+        neighbors: Vec<Option<Arc<Chunk>>>,
+        appearance: Arc<BlockAppearance>,
+        mode: MeshingMode,
+        /// The level of detail, 0 for every block. Coarser levels are built with `build_lod_mesh`
+        /// and ignore the meshing mode.
+        level: u8,
+    },
+}
+
+pub enum JobResult {
+    Loaded {
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk: Chunk,
+    },
+    Meshed {
+        chunk_x: i32,
+        chunk_z: i32,
+        meshes: ChunkMeshes,
+        /// Which faces of each section can be seen from which, for occlusion culling.
+        connectivity: Vec<SectionConnectivity>,
+    },
+}
+
+/// Loads, generates, lights and meshes chunks on background threads. Jobs are taken by whichever thread is free
+/// first, so results can come back in a different order than their jobs were submitted in.
+pub struct WorkerPool {
+    job_sender: Option<Sender<Job>>,
+    result_receiver: Receiver<JobResult>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(
+        thread_count: usize,
+        generator: Arc<TerrainGenerator>,
+        save: WorldSave,
+        light_properties: Arc<LightProperties>,
+    ) -> WorkerPool {
+        let (job_sender, job_receiver) = channel();
+        let (result_sender, result_receiver) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let threads = (0..thread_count)
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let generator = generator.clone();
+                let save = save.clone();
+                let light_properties = light_properties.clone();
+
+                thread::spawn(move || loop {
+                    // the lock is only held while waiting for a job, not while running it
+                    let job = match job_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+
+                    if result_sender.send(run_job(job, &*generator, &save, &light_properties)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+
+        WorkerPool {
+            job_sender: Some(job_sender),
+            result_receiver: result_receiver,
+            threads: threads,
+        }
+    }
+
+    pub fn submit(&self, job: Job) {
+        self.job_sender.as_ref().unwrap().send(job).unwrap();
+    }
+
+    /// The results of every job that finished since the last poll, without waiting for more.
+    pub fn poll(&self) -> Vec<JobResult> {
+        self.result_receiver.try_iter().collect()
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // closing the channel makes every thread return once it's done with its current job
+        self.job_sender = None;
+
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap();
+        }
+    }
+}
+
+fn run_job(job: Job, generator: &TerrainGenerator, save: &WorldSave, light_properties: &LightProperties) -> JobResult {
+    match job {
+        Job::Load { chunk_x, chunk_z } => {
+            let saved = match save.load_chunk(chunk_x, chunk_z, &|block| light_properties.is_defined(block)) {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    // the chunk is generated again, and overwrites the broken file once it's unloaded
+                    println!("Failed to load chunk {}, {}: {}", chunk_x, chunk_z, error);
+                    None
+                }
+            };
+
+            let mut chunk = saved.unwrap_or_else(|| generator.generate_chunk(chunk_x, chunk_z));
+            light_chunk(&mut chunk, light_properties);
+
+            JobResult::Loaded {
+                chunk_x: chunk_x,
+                chunk_z: chunk_z,
+                chunk: chunk,
+            }
+        }
+        Job::Mesh {
+            chunk_x,
+            chunk_z,
+            center,
+            neighbors,
+            appearance,
+            mode,
+            level,
+        } => {
+            let neighborhood = ChunkNeighborhood::new(&center, &neighbors);
+            let meshes = if level == 0 {
+                build_chunk_mesh(&neighborhood, chunk_x, chunk_z, &appearance, mode)
+            } else {
+                build_lod_mesh(&neighborhood, chunk_x, chunk_z, &appearance, level)
+            };
+
+            JobResult::Meshed {
+                chunk_x: chunk_x,
+                chunk_z: chunk_z,
+                meshes: meshes,
+                connectivity: get_chunk_connectivity(&center, &appearance),
+            }
+        }
+    }
+}
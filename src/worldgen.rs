@@ -0,0 +1,644 @@
+use biome::{get_dominant_biome, Biome, BiomeSource, BIOMES};
+use game::{create_chunk, BlockType, Chunk, World};
+use noise::{NoiseFn, Perlin};
+
+/// Columns whose terrain is lower than this are filled up to it with water.
+pub const SEA_LEVEL: i32 = 64;
+const HEIGHT_FREQUENCY: f64 = 1.0 / 64.0;
+const HEIGHT_OCTAVES: usize = 4;
+const FILLER_DEPTH: i32 = 4;
+
+/// A step of generation that runs on a chunk after its heightmap was filled in, e.g. to carve caves
+/// or place ores.
+pub trait GenerationPass: Send + Sync {
+    fn apply(&self, chunk: &mut Chunk, chunk_x: i32, chunk_z: i32);
+}
+
+/// Places features such as trees or structures into a generated chunk once it and the eight chunks
+/// around it are in the world. Features may reach into those neighbours, but no further, and have to
+/// be placed with `World::set_feature_block`. Chunks are decorated in whatever order they finish
+/// loading, so features must not depend on what the world's neighbours already contain: the
+/// ground is taken from the `Heightmap` rather than the world's highest block, which may be a
+/// neighbour's tree.
+pub trait Decorator: Send + Sync {
+    fn decorate(&self, world: &mut World, heightmap: &Heightmap, chunk_x: i32, chunk_z: i32);
+}
+
+/// The ground as it is generated, before anything is carved out of it or placed on it. It only
+/// depends on the seed and the column.
+pub trait Heightmap {
+    /// The y coordinate of the top block of a column's terrain.
+    fn get_ground(&self, x: i32, z: i32) -> i32;
+}
+
+/// Fills chunks with terrain. Generators must be deterministic: the same chunk coordinates always
+/// have to produce the same chunk. Chunks are generated on worker threads, while decorating
+/// happens on the main thread.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate_chunk(&self, chunk_x: i32, chunk_z: i32) -> Chunk;
+
+    /// Called once for every chunk made by `generate_chunk`, as soon as it and its neighbours are in
+    /// the world. `World::finish_features` is called after it.
+    fn decorate(&self, _world: &mut World, _chunk_x: i32, _chunk_z: i32) {}
+}
+
+/// Fractal noise made of several octaves of Perlin noise, each with double the frequency and half
+/// the amplitude of the previous one. Output is roughly in the range -1 to 1.
+pub struct OctaveNoise {
+    octaves: Vec<Perlin>,
+    frequency: f64,
+    persistence: f64,
+    lacunarity: f64,
+}
+
+impl OctaveNoise {
+    pub fn new(seed: u64, octaves: usize, frequency: f64) -> OctaveNoise {
+        OctaveNoise {
+            octaves: (0..octaves)
+                .map(|octave| Perlin::new(hash_seed(seed, octave as u64) as u32))
+                .collect(),
+            frequency: frequency,
+            persistence: 0.5,
+            lacunarity: 2.0,
+        }
+    }
+
+    pub fn get(&self, x: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut max_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+
+        for octave in &self.octaves {
+            total += octave.get([x * frequency, z * frequency]) * amplitude;
+            max_amplitude += amplitude;
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity;
+        }
+
+        total / max_amplitude
+    }
+}
+
+/// Generates terrain shaped by its biomes: each column gets a few blocks of the biome's surface and
+/// filler blocks, with stone below. Columns below `SEA_LEVEL` are filled with water, and get filler
+/// instead of the surface block on their bottom.
+pub struct DefaultTerrainGenerator {
+    pub seed: u64,
+    pub biome_source: BiomeSource,
+    /// Run in order on every chunk after its heightmap was filled in.
+    pub passes: Vec<Box<GenerationPass>>,
+    pub decorators: Vec<Box<Decorator>>,
+    height_noise: OctaveNoise,
+}
+
+impl DefaultTerrainGenerator {
+    pub fn new(seed: u64) -> DefaultTerrainGenerator {
+        DefaultTerrainGenerator {
+            seed: seed,
+            biome_source: BiomeSource::new(seed),
+            passes: vec![
+                Box::new(CavePass::new(seed)),
+                Box::new(OrePass::new(seed, OreSettings::defaults())),
+            ],
+            decorators: vec![
+                Box::new(TreeDecorator::new(seed)),
+                Box::new(StructureDecorator::new(seed, Structure::defaults())),
+            ],
+            height_noise: OctaveNoise::new(hash_seed(seed, 0), HEIGHT_OCTAVES, HEIGHT_FREQUENCY),
+        }
+    }
+
+    /// The height of a column, with the height settings of the biomes around it blended together by
+    /// their weights.
+    pub fn get_height(&self, x: i32, z: i32, weights: &[f64; 4]) -> i32 {
+        let noise = self.height_noise.get(x as f64 + 0.5, z as f64 + 0.5);
+        let mut height = 0.0;
+
+        for (i, biome) in BIOMES.iter().enumerate() {
+            let properties = biome.get_properties();
+            height += (properties.base_height + noise * properties.height_scale) * weights[i];
+        }
+
+        f64::max(1.0, f64::min(255.0, f64::round(height))) as i32
+    }
+}
+
+impl TerrainGenerator for DefaultTerrainGenerator {
+    fn generate_chunk(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let mut chunk = create_chunk();
+
+        for local_x in 0..16 {
+            for local_z in 0..16 {
+                let x = chunk_x * 16 + local_x;
+                let z = chunk_z * 16 + local_z;
+                let weights = self.biome_source.get_weights(x, z);
+                let height = self.get_height(x, z, &weights);
+                let biome = get_dominant_biome(&weights);
+                let properties = biome.get_properties();
+
+                chunk.set_biome(local_x as usize, local_z as usize, biome);
+
+                for y in 0..height {
+                    let block = if y == height - 1 && height >= SEA_LEVEL {
+                        properties.surface_block
+                    } else if y >= height - 1 - FILLER_DEPTH {
+                        properties.filler_block
+                    } else {
+                        BlockType::Stone as u16
+                    };
+                    chunk.set_block(local_x as usize, y as usize, local_z as usize, block);
+                }
+
+                for y in height..SEA_LEVEL {
+                    chunk.set_block(local_x as usize, y as usize, local_z as usize, BlockType::Water as u16);
+                }
+            }
+        }
+
+        for pass in &self.passes {
+            pass.apply(&mut chunk, chunk_x, chunk_z);
+        }
+
+        chunk
+    }
+
+    fn decorate(&self, world: &mut World, chunk_x: i32, chunk_z: i32) {
+        for decorator in &self.decorators {
+            decorator.decorate(world, self, chunk_x, chunk_z);
+        }
+    }
+}
+
+impl Heightmap for DefaultTerrainGenerator {
+    fn get_ground(&self, x: i32, z: i32) -> i32 {
+        self.get_height(x, z, &self.biome_source.get_weights(x, z)) - 1
+    }
+}
+
+const CAVE_FREQUENCY: f64 = 1.0 / 48.0;
+const CAVE_VERTICAL_SQUASH: f64 = 2.0;
+const CAVE_THICKNESS: f64 = 0.06;
+const CAVE_MIN_Y: usize = 5;
+
+/// Carves winding tunnels where the zero surfaces of two 3D noise fields cross. The noise is sampled
+/// at world coordinates, so tunnels continue seamlessly into neighbouring chunks. Tunnels stop short
+/// of water above them, so that seas don't drain into them.
+pub struct CavePass {
+    first_noise: Perlin,
+    second_noise: Perlin,
+}
+
+impl CavePass {
+    pub fn new(seed: u64) -> CavePass {
+        CavePass {
+            first_noise: Perlin::new(hash_seed(seed, 3) as u32),
+            second_noise: Perlin::new(hash_seed(seed, 4) as u32),
+        }
+    }
+
+    pub fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let point = [
+            x as f64 * CAVE_FREQUENCY,
+            y as f64 * CAVE_FREQUENCY * CAVE_VERTICAL_SQUASH,
+            z as f64 * CAVE_FREQUENCY,
+        ];
+
+        f64::abs(self.first_noise.get(point)) < CAVE_THICKNESS
+            && f64::abs(self.second_noise.get(point)) < CAVE_THICKNESS
+    }
+}
+
+impl GenerationPass for CavePass {
+    fn apply(&self, chunk: &mut Chunk, chunk_x: i32, chunk_z: i32) {
+        for local_x in 0..16 {
+            for local_z in 0..16 {
+                let x = chunk_x * 16 + local_x as i32;
+                let z = chunk_z * 16 + local_z as i32;
+
+                for y in CAVE_MIN_Y..256 {
+                    let block = chunk.get_block(local_x, y, local_z);
+                    if block != BlockType::Air as u16
+                        && block != BlockType::Water as u16
+                        && (y == 255 || chunk.get_block(local_x, y + 1, local_z) != BlockType::Water as u16)
+                        && self.is_cave(x, y as i32, z)
+                    {
+                        chunk.set_block(local_x, y, local_z, BlockType::Air as u16);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct OreSettings {
+    pub block: u16,
+    pub min_y: i32,
+    pub max_y: i32,
+    /// How many blocks a vein tries to place.
+    pub vein_size: u32,
+    pub veins_per_chunk: u32,
+}
+
+impl OreSettings {
+    pub fn defaults() -> Vec<OreSettings> {
+        vec![
+            OreSettings {
+                block: BlockType::CoalOre as u16,
+                min_y: 5,
+                max_y: 128,
+                vein_size: 12,
+                veins_per_chunk: 20,
+            },
+            OreSettings {
+                block: BlockType::IronOre as u16,
+                min_y: 5,
+                max_y: 64,
+                vein_size: 8,
+                veins_per_chunk: 12,
+            },
+            OreSettings {
+                block: BlockType::GoldOre as u16,
+                min_y: 5,
+                max_y: 32,
+                vein_size: 6,
+                veins_per_chunk: 3,
+            },
+            OreSettings {
+                block: BlockType::DiamondOre as u16,
+                min_y: 5,
+                max_y: 16,
+                vein_size: 5,
+                veins_per_chunk: 1,
+            },
+        ]
+    }
+}
+
+/// Replaces stone with veins of ore. Every vein is a short random walk that starts inside of the
+/// chunk; blocks of it that would leave the chunk are skipped.
+pub struct OrePass {
+    seed: u64,
+    ores: Vec<OreSettings>,
+}
+
+impl OrePass {
+    pub fn new(seed: u64, ores: Vec<OreSettings>) -> OrePass {
+        OrePass {
+            seed: hash_seed(seed, 5),
+            ores: ores,
+        }
+    }
+}
+
+impl GenerationPass for OrePass {
+    fn apply(&self, chunk: &mut Chunk, chunk_x: i32, chunk_z: i32) {
+        let mut random = SeededRandom::new(get_chunk_seed(self.seed, chunk_x, chunk_z));
+
+        for ore in &self.ores {
+            for _ in 0..ore.veins_per_chunk {
+                let mut x = random.next_int(16);
+                let mut y = ore.min_y + random.next_int(ore.max_y - ore.min_y + 1);
+                let mut z = random.next_int(16);
+
+                for _ in 0..ore.vein_size {
+                    if x >= 0 && x < 16 && z >= 0 && z < 16 && y >= 0 && y < 256 {
+                        let (local_x, local_z) = (x as usize, z as usize);
+                        if chunk.get_block(local_x, y as usize, local_z) == BlockType::Stone as u16 {
+                            chunk.set_block(local_x, y as usize, local_z, ore.block);
+                        }
+                    }
+
+                    match random.next_int(6) {
+                        0 => x += 1,
+                        1 => x -= 1,
+                        2 => y += 1,
+                        3 => y -= 1,
+                        4 => z += 1,
+                        _ => z -= 1,
+                    }
+                }
+            }
+        }
+    }
+}
+
+const TREE_ATTEMPTS_PER_CHUNK: u32 = 6;
+
+/// Grows trees on grass. Every tree's trunk stands in the chunk being decorated, but its leaves
+/// may spill into the neighbouring chunks. Features never replace grass, so whether a column has
+/// grass on its ground doesn't depend on the trees around it.
+pub struct TreeDecorator {
+    seed: u64,
+}
+
+impl TreeDecorator {
+    pub fn new(seed: u64) -> TreeDecorator {
+        TreeDecorator {
+            seed: hash_seed(seed, 6),
+        }
+    }
+
+    fn get_tree_chance(biome: Option<Biome>) -> f64 {
+        match biome {
+            Some(Biome::Plains) => 0.4,
+            Some(Biome::Mountains) => 0.15,
+            _ => 0.0,
+        }
+    }
+
+    fn place_tree(world: &mut World, random: &mut SeededRandom, x: i32, y: i32, z: i32, trunk_height: i32) {
+        for dy in 0..trunk_height {
+            world.set_feature_block(x, y + dy, z, BlockType::Log as u16);
+        }
+
+        for dy in trunk_height - 3..trunk_height + 1 {
+            let radius = if dy >= trunk_height - 1 { 1 } else { 2 };
+
+            for dx in -radius..radius + 1 {
+                for dz in -radius..radius + 1 {
+                    let corner = i32::abs(dx) == radius && i32::abs(dz) == radius;
+                    // the corners are left out randomly on the wide layers and always on the top layer
+                    // to round off the crown
+                    let skip = corner && (dy == trunk_height || random.next_int(2) == 0);
+
+                    if !skip {
+                        world.set_feature_block(x + dx, y + dy, z + dz, BlockType::Leaves as u16);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Decorator for TreeDecorator {
+    fn decorate(&self, world: &mut World, heightmap: &Heightmap, chunk_x: i32, chunk_z: i32) {
+        let mut random = SeededRandom::new(get_chunk_seed(self.seed, chunk_x, chunk_z));
+
+        for _ in 0..TREE_ATTEMPTS_PER_CHUNK {
+            let x = chunk_x * 16 + random.next_int(16);
+            let z = chunk_z * 16 + random.next_int(16);
+            let trunk_height = 4 + random.next_int(3);
+
+            if random.next_f64() >= TreeDecorator::get_tree_chance(world.get_biome(x, z)) {
+                continue;
+            }
+
+            let ground = heightmap.get_ground(x, z);
+            if ground + trunk_height + 2 > 255
+                || world.get_block_id(x, ground, z) != BlockType::Grass as u16
+            {
+                continue;
+            }
+
+            TreeDecorator::place_tree(world, &mut random, x, ground + 1, z, trunk_height);
+        }
+    }
+}
+
+/// A small prefab made of blocks relative to its origin, which is placed on top of the ground.
+pub struct Structure {
+    /// The x, y and z offsets from the origin and the block for each block of the structure.
+    pub blocks: Vec<(i32, i32, i32, u16)>,
+}
+
+impl Structure {
+    pub fn defaults() -> Vec<Structure> {
+        vec![Structure::ruin(), Structure::boulder()]
+    }
+
+    /// A crumbling ring of cobblestone walls with a doorway.
+    pub fn ruin() -> Structure {
+        let mut blocks = Vec::new();
+        let wall_heights = [3, 2, 3, 1, 2, 3, 3, 2, 1, 2, 3, 2, 2, 3, 1, 3];
+        let mut wall = 0;
+
+        for dx in 0..5 {
+            for dz in 0..5 {
+                if dx != 0 && dx != 4 && dz != 0 && dz != 4 {
+                    continue;
+                }
+
+                let is_doorway = dx == 2 && dz == 0;
+                for dy in 0..wall_heights[wall % wall_heights.len()] {
+                    if !is_doorway || dy >= 2 {
+                        blocks.push((dx, dy, dz, BlockType::Cobblestone as u16));
+                    }
+                }
+
+                wall += 1;
+            }
+        }
+
+        Structure { blocks: blocks }
+    }
+
+    pub fn boulder() -> Structure {
+        let mut blocks = Vec::new();
+
+        for dx in -1..2 {
+            for dz in -1..2 {
+                blocks.push((dx, 0, dz, BlockType::Stone as u16));
+                if dx == 0 || dz == 0 {
+                    blocks.push((dx, 1, dz, BlockType::Cobblestone as u16));
+                }
+            }
+        }
+        blocks.push((0, 2, 0, BlockType::Stone as u16));
+
+        Structure { blocks: blocks }
+    }
+}
+
+const STRUCTURE_CHANCE: f64 = 0.08;
+
+/// Places at most one of its structures per chunk, on dry land.
+pub struct StructureDecorator {
+    seed: u64,
+    structures: Vec<Structure>,
+}
+
+impl StructureDecorator {
+    pub fn new(seed: u64, structures: Vec<Structure>) -> StructureDecorator {
+        StructureDecorator {
+            seed: hash_seed(seed, 7),
+            structures: structures,
+        }
+    }
+}
+
+impl Decorator for StructureDecorator {
+    fn decorate(&self, world: &mut World, heightmap: &Heightmap, chunk_x: i32, chunk_z: i32) {
+        let mut random = SeededRandom::new(get_chunk_seed(self.seed, chunk_x, chunk_z));
+        if self.structures.is_empty() || random.next_f64() >= STRUCTURE_CHANCE {
+            return;
+        }
+
+        let structure = &self.structures[random.next_int(self.structures.len() as i32) as usize];
+        let x = chunk_x * 16 + random.next_int(16);
+        let z = chunk_z * 16 + random.next_int(16);
+        let ground = heightmap.get_ground(x, z);
+
+        if ground + 1 < SEA_LEVEL || ground > 250 || world.get_biome(x, z) == Some(Biome::Ocean) {
+            return;
+        }
+
+        for &(dx, dy, dz, block) in &structure.blocks {
+            world.set_feature_block(x + dx, ground + 1 + dy, z + dz, block);
+        }
+    }
+}
+
+/// How firmly a block holds its place against blocks placed by features: a feature block only
+/// replaces blocks of a lower priority, or of the same priority but a lower id. Any two features
+/// therefore overlap the same way no matter which is placed first.
+pub fn get_feature_priority(block: u16) -> u8 {
+    if block == BlockType::Air as u16 {
+        0
+    } else if block == BlockType::Leaves as u16 {
+        1
+    } else if block == BlockType::Log as u16 {
+        2
+    } else {
+        3
+    }
+}
+
+pub fn can_feature_replace(current: u16, block: u16) -> bool {
+    (get_feature_priority(block), block) > (get_feature_priority(current), current)
+}
+
+/// A small, fast random number generator (xorshift64*) whose output only depends on its seed, unlike
+/// `rand`'s generators which may change between versions.
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom {
+            // the state must never be 0
+            state: hash_seed(seed, 0) | 1,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A random number from 0 up to, but not including, `bound`.
+    pub fn next_int(&mut self, bound: i32) -> i32 {
+        (self.next_u64() % bound as u64) as i32
+    }
+
+    /// A random number from 0 up to, but not including, 1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A seed for the random parts of a chunk's generation, derived from the seed of the pass.
+pub fn get_chunk_seed(seed: u64, chunk_x: i32, chunk_z: i32) -> u64 {
+    hash_seed(hash_seed(seed, chunk_x as u64), chunk_z as u64)
+}
+
+/// Mixes a seed with a salt into a new, well distributed seed (SplitMix64), so that every noise
+/// layer and random pass of the generator gets its own seed from the world seed.
+pub fn hash_seed(seed: u64, salt: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(salt.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytebuffer::ByteBuffer;
+    use chunk::BlockData;
+    use game::{create_world, Blocks};
+    use lighting::light_chunk;
+
+    fn generate_bytes(seed: u64, chunk_x: i32, chunk_z: i32) -> Vec<u8> {
+        let chunk = DefaultTerrainGenerator::new(seed).generate_chunk(chunk_x, chunk_z);
+        let mut buf = ByteBuffer::new();
+        chunk.to_buffer(&mut buf);
+        buf.to_bytes()
+    }
+
+    #[test]
+    fn test_same_seed_same_chunk() {
+        assert_eq!(generate_bytes(1234, 3, -7), generate_bytes(1234, 3, -7));
+        assert_eq!(generate_bytes(0, 0, 0), generate_bytes(0, 0, 0));
+    }
+
+    #[test]
+    fn test_different_seed_different_chunk() {
+        assert!(generate_bytes(1234, 3, -7) != generate_bytes(1235, 3, -7));
+    }
+
+    /// The blocks of a 5×5 area after decorating its middle 3×3 chunks in the given order.
+    fn decorate_area(generator: &DefaultTerrainGenerator, order: &[(i32, i32)]) -> Vec<BlockData> {
+        let blocks = Blocks::from_json(include_str!("../data/blocks.json")).unwrap();
+        let mut world = create_world(2, &blocks);
+        for chunk_x in -2..3 {
+            for chunk_z in -2..3 {
+                let mut chunk = generator.generate_chunk(chunk_x, chunk_z);
+                light_chunk(&mut chunk, &world.get_light_properties());
+                world.insert_chunk(chunk_x, chunk_z, chunk);
+            }
+        }
+
+        for &(chunk_x, chunk_z) in order {
+            generator.decorate(&mut world, chunk_x, chunk_z);
+            world.finish_features();
+        }
+
+        // compared block by block, since the order of a section's palette depends on the order
+        // its blocks were placed in
+        let mut blocks = Vec::new();
+        for x in -32..48 {
+            for z in -32..48 {
+                for y in 0..256 {
+                    blocks.push(world.get_block_data(x, y, z));
+                }
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_decoration_order_does_not_matter() {
+        let mut order = Vec::new();
+        for chunk_x in -1..2 {
+            for chunk_z in -1..2 {
+                order.push((chunk_x, chunk_z));
+            }
+        }
+        let mut reversed = order.clone();
+        reversed.reverse();
+        let mut shuffled = order.clone();
+        shuffled.sort_by_key(|&(chunk_x, chunk_z)| (chunk_z * 7 + chunk_x * 3) & 3);
+
+        for &seed in [1, 2].iter() {
+            let generator = DefaultTerrainGenerator::new(seed);
+            let blocks = decorate_area(&generator, &order);
+            assert!(blocks == decorate_area(&generator, &reversed), "seed {}", seed);
+            assert!(blocks == decorate_area(&generator, &shuffled), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_hash_seed() {
+        assert_eq!(hash_seed(42, 7), hash_seed(42, 7));
+        assert!(hash_seed(42, 7) != hash_seed(43, 7));
+        assert!(hash_seed(42, 7) != hash_seed(42, 8));
+    }
+}